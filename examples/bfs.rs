@@ -11,7 +11,6 @@ use differential_dataflow::input::Input;
 use differential_dataflow::Collection;
 use differential_dataflow::operators::*;
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::logging::DifferentialEvent;
 
 type Node = u32;
 type Edge = (Node, Node);
@@ -27,20 +26,19 @@ fn main() {
     // define a new computational scope, in which to run BFS
     timely::execute_from_args(std::env::args(), move |worker| {
 
-        if let Ok(addr) = ::std::env::var("DIFFERENTIAL_LOG_ADDR") {
+        // Optionally log to st2 via TCP (`ST2_LOG_ADDR`) or to `*.dump` files in `ST2_LOG_DIR`.
+        // Otherwise, Timely's own logging (`TIMELY_WORKER_LOG_ADDR`) is left untouched.
+        if let Ok(addr) = ::std::env::var("ST2_LOG_ADDR") {
+            eprintln!("enabled logging to {}", addr);
+            st2::logging::install_tcp_logger(worker, addr).expect("couldn't connect to st2");
+        } else if let Ok(dir) = ::std::env::var("ST2_LOG_DIR") {
+            eprintln!("enabled logging to {}", dir);
+            st2::logging::install_file_logger(worker, dir).expect("couldn't create log files");
+        }
 
+        if let Ok(addr) = ::std::env::var("DIFFERENTIAL_LOG_ADDR") {
             eprintln!("enabled DIFFERENTIAL logging to {}", addr);
-
-            if let Ok(stream) = ::std::net::TcpStream::connect(&addr) {
-                let writer = ::timely::dataflow::operators::capture::EventWriter::new(stream);
-                let mut logger = ::timely::logging::BatchLogger::new(writer);
-                worker.log_register().insert::<DifferentialEvent,_>("differential/arrange", move |time, data|
-                    logger.publish_batch(time, data)
-                );
-            }
-            else {
-                panic!("Could not connect to differential log address: {:?}", addr);
-            }
+            st2::logging::install_differential_tcp_logger(worker, addr).expect("couldn't connect to differential log address");
         }
 
         let timer = ::std::time::Instant::now();
@@ -100,7 +98,7 @@ fn main() {
             graph.flush();
 
             let timer2 = ::std::time::Instant::now();
-            worker.step_while(|| probe.less_than(&graph.time()));
+            worker.step_while(|| probe.less_than(graph.time()));
 
             if worker.index() == 0 {
                 let elapsed = timer2.elapsed();
@@ -1,33 +1,15 @@
-use timely::dataflow::operators::capture::EventWriter;
 use timely::dataflow::operators::{Exchange, Input, Inspect, Probe};
 use timely::dataflow::InputHandle;
-use timely::logging::BatchLogger;
-use timely::logging::TimelyEvent;
-
-use std::fs::File;
-use std::path::Path;
 
 fn main() {
     timely::execute_from_args(std::env::args(), |worker| {
-        // Optionally enable offline logging
-        if let Err(_) = ::std::env::var("TIMELY_WORKER_LOG_ADDR") {
+        // Optionally log to st2 via TCP (`ST2_LOG_ADDR`) or to `*.dump` files in `ST2_LOG_DIR`.
+        // Otherwise, Timely's own logging (`TIMELY_WORKER_LOG_ADDR`) is left untouched.
+        if let Ok(addr) = ::std::env::var("ST2_LOG_ADDR") {
+            st2::logging::install_tcp_logger(worker, addr).expect("couldn't connect to st2");
+        } else if let Ok(dir) = ::std::env::var("ST2_LOG_DIR") {
             println!("write to file");
-
-            let name = format!("{}.dump", worker.index());
-            let path = Path::new(&name);
-            let file = match File::create(&path) {
-                Err(why) => panic!("couldn't create {}: {}", path.display(), why),
-                Ok(file) => file,
-            };
-
-            let writer = EventWriter::<_, _, _>::new(file);
-            let mut logger = BatchLogger::new(writer);
-
-            worker
-                .log_register()
-                .insert::<TimelyEvent, _>("timely", move |time, data| {
-                    logger.publish_batch(time, data)
-                });
+            st2::logging::install_file_logger(worker, dir).expect("couldn't create log files");
         }
 
         // Some computation
@@ -0,0 +1,256 @@
+//! Critical paths through the PAG: the chain of activities that determined how
+//! long a window took. Waiting and parked edges don't contribute to a path's
+//! length, s.t. the path follows the remote activity a worker waited for instead.
+
+#![deny(missing_docs)]
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, PagNode, EdgeKind};
+use crate::analysis::{Analysis, window_start};
+use crate::topology::Topology;
+
+/// A path through the PAG
+#[derive(Clone, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct CriticalPath {
+    /// The path's edges, in order
+    pub edges: Vec<PagEdge>,
+    /// Sum of the edges' weights
+    pub length: Duration,
+}
+
+/// Critical path within a window
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct WindowCriticalPath {
+    /// Start of the window
+    pub window: Duration,
+    /// The critical path
+    pub path: CriticalPath,
+}
+
+/// Weight of an edge on a critical path: its duration, unless
+/// the worker is idle (waiting or parked) during it
+pub fn weight(edge: &PagEdge) -> Duration {
+    match edge.edge_type.kind() {
+        kind if kind.is_waiting() => Duration::default(),
+        EdgeKind::Parked => Duration::default(),
+        _ => edge.duration(),
+    }
+}
+
+/// Longest path through `edges` w.r.t. `weight`. Edges whose destination
+/// precedes their source (remote edges affected by clock skew) are ignored.
+pub fn longest_path<'a, I, F>(edges: I, weight: F) -> CriticalPath
+where I: IntoIterator<Item = &'a PagEdge>, F: Fn(&PagEdge) -> Duration
+{
+    let edges: Vec<&PagEdge> = edges.into_iter().filter(|e| e.dst.t >= e.src.t && e.src != e.dst).collect();
+
+    let mut outgoing: HashMap<PagNode, Vec<usize>> = HashMap::new();
+    let mut indegree: HashMap<PagNode, usize> = HashMap::new();
+    for (idx, edge) in edges.iter().enumerate() {
+        outgoing.entry(edge.src).or_default().push(idx);
+        indegree.entry(edge.src).or_default();
+        *indegree.entry(edge.dst).or_default() += 1;
+    }
+
+    // longest path ending at a node, and the last edge on it
+    let mut best: HashMap<PagNode, (Duration, Option<usize>)> = HashMap::new();
+    let mut queue: VecDeque<PagNode> = indegree.iter().filter(|(_, d)| **d == 0).map(|(n, _)| *n).collect();
+
+    while let Some(node) = queue.pop_front() {
+        let (length, _) = *best.entry(node).or_default();
+        for idx in outgoing.get(&node).into_iter().flatten() {
+            let edge = edges[*idx];
+            let candidate = length + weight(edge);
+            let dst = best.entry(edge.dst).or_default();
+            if dst.1.is_none() || candidate > dst.0 {
+                *dst = (candidate, Some(*idx));
+            }
+
+            let degree = indegree.get_mut(&edge.dst).expect("node without indegree");
+            *degree -= 1;
+            if *degree == 0 {
+                queue.push_back(edge.dst);
+            }
+        }
+    }
+
+    let end = best.iter()
+        .max_by_key(|(node, (length, _))| (*length, node.t))
+        .map(|(node, _)| *node);
+
+    let mut path = Vec::new();
+    let mut current = end;
+    while let Some(node) = current {
+        match best.get(&node) {
+            Some((_, Some(idx))) => {
+                path.push(edges[*idx].clone());
+                current = Some(edges[*idx].src);
+            }
+            _ => current = None,
+        }
+    }
+    path.reverse();
+
+    CriticalPath {
+        length: end.and_then(|node| best.get(&node)).map(|(length, _)| *length).unwrap_or_default(),
+        edges: path,
+    }
+}
+
+/// Edges by the window they start in, leaving out edges crossing its end
+pub(crate) struct WindowedEdges {
+    window: Duration,
+    windows: BTreeMap<Duration, Vec<PagEdge>>,
+}
+
+impl WindowedEdges {
+    pub(crate) fn new(window: Duration) -> Self {
+        WindowedEdges { window, windows: BTreeMap::new() }
+    }
+
+    pub(crate) fn insert(&mut self, edge: &PagEdge) {
+        let w = window_start(edge.src.t, self.window);
+        if edge.dst.t <= w + self.window {
+            self.windows.entry(w).or_default().push(edge.clone());
+        }
+    }
+
+    /// Removes and returns the windows ending before `until`, which are
+    /// complete once the PAG is complete up to `until`
+    pub(crate) fn complete(&mut self, until: Duration) -> BTreeMap<Duration, Vec<PagEdge>> {
+        match until.checked_sub(self.window) {
+            Some(end) => {
+                let open = self.windows.split_off(&end);
+                std::mem::replace(&mut self.windows, open)
+            }
+            None => BTreeMap::new(),
+        }
+    }
+}
+
+/// Incrementally computes the critical path of every window. Edges are assigned
+/// to the window they start in; edges crossing its end are left out. Windows are
+/// reported once the PAG is complete up to their end.
+/// This relies on the untrimmed PAG: trimming merges away the ends of local edges
+/// that remote edges are sent from or received at, disconnecting the workers.
+pub struct CriticalPaths {
+    windows: WindowedEdges,
+}
+
+impl CriticalPaths {
+    /// Creates an analysis of critical paths per window of size `window`.
+    pub fn new(window: Duration) -> Self {
+        CriticalPaths { windows: WindowedEdges::new(window) }
+    }
+
+    fn paths(windows: BTreeMap<Duration, Vec<PagEdge>>) -> Vec<WindowCriticalPath> {
+        windows.into_iter()
+            .map(|(window, edges)| WindowCriticalPath { window, path: longest_path(edges.iter(), weight) })
+            .collect()
+    }
+}
+
+impl Analysis for CriticalPaths {
+    type Output = WindowCriticalPath;
+
+    fn update(&mut self, edge: &PagEdge) {
+        self.windows.insert(edge);
+    }
+
+    fn advance(&mut self, until: Duration, _topology: &Topology) -> Vec<WindowCriticalPath> {
+        CriticalPaths::paths(self.windows.complete(until))
+    }
+
+    fn finish(&mut self, _topology: &Topology) -> Vec<WindowCriticalPath> {
+        CriticalPaths::paths(self.windows.complete(Duration::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EdgeType, WaitingCause};
+    use crate::testing::{edge, processing, log_events, dataflow_pag, run_incrementally};
+
+    use timely::dataflow::InputHandle;
+    use timely::dataflow::operators::{Exchange, Input, Map, Probe};
+
+    #[test]
+    fn longest_path_skips_idle_time() {
+        let pag = [
+            edge((0, 0), (10, 0), processing(1)),
+            edge((10, 0), (30, 0), EdgeType::Waiting(WaitingCause::Data)),
+            edge((0, 1), (20, 1), processing(1)),
+            edge((20, 1), (30, 0), EdgeType::Data { length: 1, channel: 2 }),
+            edge((30, 0), (40, 0), processing(1)),
+            // received before it was sent due to clock skew
+            edge((25, 1), (24, 0), EdgeType::Progress),
+        ];
+
+        let path = longest_path(pag.iter(), weight);
+        assert_eq!(path.length, Duration::from_nanos(40));
+        assert_eq!(path.edges, vec![pag[2].clone(), pag[3].clone(), pag[4].clone()]);
+    }
+
+    #[test]
+    fn longest_path_of_nothing() {
+        assert_eq!(longest_path(std::iter::empty(), weight), CriticalPath::default());
+    }
+
+    #[test]
+    fn reports_path_of_each_complete_window() {
+        let topology = Topology::default();
+        let mut paths = CriticalPaths::new(Duration::from_nanos(100));
+        paths.update(&edge((0, 0), (50, 0), processing(1)));
+        paths.update(&edge((50, 0), (100, 0), processing(1)));
+        // crosses the end of its window
+        paths.update(&edge((100, 0), (210, 0), processing(1)));
+
+        assert!(paths.advance(Duration::from_nanos(100), &topology).is_empty());
+        let reported = paths.advance(Duration::from_nanos(101), &topology);
+        assert_eq!(reported.len(), 1);
+        assert_eq!((reported[0].window, reported[0].path.length, reported[0].path.edges.len()), (Duration::default(), Duration::from_nanos(100), 2));
+        assert!(paths.finish(&topology).is_empty());
+    }
+
+    #[test]
+    fn follows_data_across_workers() {
+        // worker 1 works for a while on data that worker 0 waits for
+        let events = log_events(2, |worker| {
+            let mut input = InputHandle::new();
+            let probe = worker.dataflow::<u64, _, _>(|scope| {
+                scope.input_from(&mut input)
+                    .map(|x: u64| {
+                        std::thread::sleep(std::time::Duration::from_millis(2));
+                        x
+                    })
+                    .exchange(|_| 0)
+                    .probe()
+            });
+
+            for round in 0 .. 3 {
+                if worker.index() == 1 {
+                    input.send(round);
+                }
+                input.advance_to(round + 1);
+                while probe.less_than(input.time()) {
+                    worker.step();
+                }
+            }
+        });
+
+        let pag = dataflow_pag(events, false);
+        let paths = run_incrementally(CriticalPaths::new(Duration::from_secs(10)), &pag, Duration::from_millis(1), &Topology::default());
+        assert_eq!(paths.len(), 1);
+        let path = &paths[0].path;
+
+        // a connected path, through worker 1's work and the data it sent to worker 0
+        assert!(path.edges.windows(2).all(|w| w[0].dst == w[1].src));
+        assert!(path.length >= Duration::from_millis(6));
+        assert!(path.edges.iter().any(|e| (e.src.wid, e.dst.wid) == (1, 0) && e.edge_type.kind() == EdgeKind::Data));
+    }
+}
@@ -0,0 +1,177 @@
+//! Log-linear duration histograms in bounded memory (in the spirit of HDR histograms):
+//! every power of two is split into `SUB_BUCKETS` buckets of equal width,
+//! s.t. recorded durations are accurate to within ~3%, up to `u64::MAX` ns.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+/// log2 of the number of buckets per power of two
+const SUB_BUCKET_BITS: u32 = 5;
+/// #buckets per power of two
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// Distribution of recorded durations
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Histogram {
+    /// #durations by bucket index, at most ~2k buckets
+    buckets: BTreeMap<usize, u64>,
+    count: u64,
+    max: Duration,
+}
+
+/// Percentiles of a histogram. Percentiles are upper bounds of
+/// the bucket they fall into, capped at the exact maximum.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Percentiles {
+    /// #recorded durations
+    pub count: u64,
+    /// median
+    pub p50: Duration,
+    /// 90th percentile
+    pub p90: Duration,
+    /// 99th percentile
+    pub p99: Duration,
+    /// maximum
+    pub max: Duration,
+}
+
+impl Histogram {
+    /// Records a duration.
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        *self.buckets.entry(bucket(nanos)).or_default() += 1;
+        self.count += 1;
+        self.max = std::cmp::max(self.max, duration);
+    }
+
+    /// Adds all durations recorded in `other`.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (idx, count) in other.buckets.iter() {
+            *self.buckets.entry(*idx).or_default() += count;
+        }
+        self.count += other.count;
+        self.max = std::cmp::max(self.max, other.max);
+    }
+
+    /// #recorded durations
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The duration below which a fraction `q` (in `[0, 1]`) of recorded durations fall.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = ((q * self.count as f64).ceil() as u64).clamp(1, self.count.max(1));
+
+        let mut seen = 0;
+        for (idx, count) in self.buckets.iter() {
+            seen += count;
+            if seen >= rank {
+                return std::cmp::min(Duration::from_nanos(bucket_upper(*idx)), self.max);
+            }
+        }
+
+        self.max
+    }
+
+    /// p50, p90, p99 and max of recorded durations
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            count: self.count,
+            p50: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p99: self.quantile(0.99),
+            max: self.max,
+        }
+    }
+}
+
+/// Index of the bucket `nanos` falls into
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS {
+        return nanos as usize;
+    }
+
+    let exp = 63 - nanos.leading_zeros();
+    let shift = exp - SUB_BUCKET_BITS;
+    let mantissa = nanos >> shift;
+    ((shift as u64 + 1) * SUB_BUCKETS + mantissa - SUB_BUCKETS) as usize
+}
+
+/// Largest value in bucket `idx`
+fn bucket_upper(idx: usize) -> u64 {
+    let idx = idx as u64;
+    if idx < SUB_BUCKETS {
+        return idx;
+    }
+
+    let shift = idx / SUB_BUCKETS - 1;
+    let mantissa = idx % SUB_BUCKETS + SUB_BUCKETS;
+    let upper = ((mantissa as u128 + 1) << shift) - 1;
+    u64::try_from(upper).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_values_are_exact() {
+        for nanos in 0 .. 2 * SUB_BUCKETS {
+            assert_eq!(bucket_upper(bucket(nanos)), nanos);
+        }
+    }
+
+    #[test]
+    fn buckets_bound_values_closely() {
+        let mut nanos = 1;
+        while nanos < u64::MAX / 3 {
+            for value in [nanos, nanos + 1, 2 * nanos - 1, 3 * nanos] {
+                let upper = bucket_upper(bucket(value));
+                assert!(upper >= value, "{} above its bucket", value);
+                assert!((upper - value) as f64 <= value as f64 / SUB_BUCKETS as f64, "{} in too wide a bucket", value);
+                // the next bucket starts right after
+                assert_eq!(bucket(upper + 1), bucket(value) + 1);
+            }
+            nanos *= 3;
+        }
+        assert_eq!(bucket_upper(bucket(u64::MAX)), u64::MAX);
+    }
+
+    #[test]
+    fn percentiles_are_bucket_bounds() {
+        let mut histogram = Histogram::default();
+        for nanos in 1 ..= 100 {
+            histogram.record(Duration::from_nanos(nanos));
+        }
+
+        assert_eq!(histogram.percentiles(), Percentiles {
+            count: 100,
+            p50: Duration::from_nanos(50),
+            // 90 shares a bucket of width 2 with 91
+            p90: Duration::from_nanos(91),
+            p99: Duration::from_nanos(99),
+            max: Duration::from_nanos(100),
+        });
+        assert_eq!(Histogram::default().percentiles(), Percentiles::default());
+    }
+
+    #[test]
+    fn merge_as_if_recorded() {
+        let durations: Vec<_> = (0 .. 1000).map(|x| Duration::from_nanos(x * x)).collect();
+
+        let mut all = Histogram::default();
+        durations.iter().for_each(|d| all.record(*d));
+
+        let (mut even, mut odd) = (Histogram::default(), Histogram::default());
+        durations.iter().step_by(2).for_each(|d| even.record(*d));
+        durations.iter().skip(1).step_by(2).for_each(|d| odd.record(*d));
+        even.merge(&odd);
+
+        assert_eq!(even, all);
+    }
+}
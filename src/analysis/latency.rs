@@ -0,0 +1,240 @@
+//! Send → receive latency of remote `Data` and `Progress` edges, per worker pair
+//! and per channel. Compared to processing times, this tells slow networks or
+//! overloaded receivers apart from slow operators.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeType, EdgeKind};
+use crate::analysis::{Analysis, window_start};
+use crate::analysis::histogram::{Histogram, Percentiles};
+use crate::topology::Topology;
+
+/// Latency of messages of one kind between two workers
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PairLatency {
+    /// Start of the window, `None` for the whole run
+    pub window: Option<Duration>,
+    /// `Data` or `Progress`
+    pub kind: EdgeKind,
+    /// Sending worker
+    pub sender: usize,
+    /// Receiving worker
+    pub receiver: usize,
+    /// Percentiles of the messages' latencies
+    pub percentiles: Percentiles,
+    /// #messages received before they were sent according to the workers' clocks,
+    /// which are recorded with zero latency
+    pub skewed: u64,
+}
+
+/// Latency of data messages on a channel, across all worker pairs
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ChannelLatency {
+    /// Start of the window, `None` for the whole run
+    pub window: Option<Duration>,
+    /// Channel ID
+    pub channel: usize,
+    /// Name of the producing operator, if known
+    pub source: Option<String>,
+    /// Name of the consuming operator, if known
+    pub target: Option<String>,
+    /// Percentiles of the messages' latencies
+    pub percentiles: Percentiles,
+}
+
+/// Remote latencies by worker pair and by channel, of a window or of the whole run
+#[derive(Clone, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct LatencyReport {
+    /// Start of the window, `None` for the whole run
+    pub window: Option<Duration>,
+    /// Latencies by kind and worker pair
+    pub pairs: Vec<PairLatency>,
+    /// Data latencies by channel
+    pub channels: Vec<ChannelLatency>,
+}
+
+/// Latency histograms of a window or of the whole run
+#[derive(Clone, Default, Debug)]
+struct Latencies {
+    /// histograms and #skewed messages by (kind, sender, receiver)
+    pairs: BTreeMap<(EdgeKind, usize, usize), (Histogram, u64)>,
+    /// histograms by channel
+    channels: BTreeMap<usize, Histogram>,
+}
+
+impl Latencies {
+    fn record(&mut self, edge: &PagEdge) {
+        if let EdgeType::Data { channel, .. } = edge.edge_type {
+            self.channels.entry(channel).or_default().record(edge.duration());
+        }
+
+        let pair = self.pairs.entry((edge.edge_type.kind(), edge.src.wid, edge.dst.wid)).or_default();
+        pair.0.record(edge.duration());
+        if edge.dst.t < edge.src.t {
+            pair.1 += 1;
+        }
+    }
+
+    fn report(&self, window: Option<Duration>, topology: &Topology) -> LatencyReport {
+        let pairs = self.pairs.iter()
+            .map(|((kind, sender, receiver), (h, skewed))| PairLatency {
+                window, kind: *kind, sender: *sender, receiver: *receiver, percentiles: h.percentiles(), skewed: *skewed,
+            })
+            .collect();
+
+        let channels = self.channels.iter()
+            .map(|(channel, h)| {
+                let operators = topology.channel_operators(*channel);
+                ChannelLatency {
+                    window,
+                    channel: *channel,
+                    source: operators.map(|(source, _)| source.name.clone()),
+                    target: operators.map(|(_, target)| target.name.clone()),
+                    percentiles: h.percentiles(),
+                }
+            })
+            .collect();
+
+        LatencyReport { window, pairs, channels }
+    }
+}
+
+/// Maintains latency histograms of remote edges. Progress edges aren't attributed
+/// to a channel in the PAG, so only data edges are broken down by channel.
+/// Messages are assigned to the window they were received in or, if the workers'
+/// clocks are skewed s.t. they were received before they were sent, the window they
+/// were sent in. This way, a window is complete once the PAG is complete up to its end.
+#[derive(Clone, Debug)]
+pub struct RemoteLatency {
+    window: Duration,
+    /// latencies by window
+    windows: BTreeMap<Duration, Latencies>,
+    /// latencies of the whole run
+    total: Latencies,
+}
+
+impl RemoteLatency {
+    /// Creates empty histograms for windows of size `window`.
+    pub fn new(window: Duration) -> Self {
+        RemoteLatency {
+            window,
+            windows: BTreeMap::new(),
+            total: Latencies::default(),
+        }
+    }
+
+    /// Incorporates a PAG edge. Only remote `Data` and `Progress` edges are recorded.
+    pub fn update(&mut self, edge: &PagEdge) {
+        let remote = edge.src.wid != edge.dst.wid;
+        if remote && matches!(edge.edge_type, EdgeType::Data { .. } | EdgeType::Progress) {
+            let window = window_start(edge.src.t.max(edge.dst.t), self.window);
+            self.windows.entry(window).or_default().record(edge);
+            self.total.record(edge);
+        }
+    }
+
+    /// Reports all windows starting before `open`, which are dropped.
+    fn report_windows(&mut self, open: Duration, topology: &Topology) -> Vec<LatencyReport> {
+        let remaining = self.windows.split_off(&open);
+        std::mem::replace(&mut self.windows, remaining).into_iter()
+            .map(|(window, latencies)| latencies.report(Some(window), topology))
+            .collect()
+    }
+}
+
+impl Analysis for RemoteLatency {
+    type Output = LatencyReport;
+
+    fn update(&mut self, edge: &PagEdge) {
+        RemoteLatency::update(self, edge);
+    }
+
+    /// Reports every window that ended by `until`.
+    fn advance(&mut self, until: Duration, topology: &Topology) -> Vec<LatencyReport> {
+        self.report_windows(window_start(until, self.window), topology)
+    }
+
+    /// Reports the remaining windows, followed by latencies of the whole run.
+    fn finish(&mut self, topology: &Topology) -> Vec<LatencyReport> {
+        let mut reports = self.report_windows(Duration::MAX, topology);
+        reports.push(self.total.report(None, topology));
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{edge, exchange_events, run_incrementally, processing};
+    use crate::offline::build_pag;
+
+    fn data(channel: usize) -> EdgeType {
+        EdgeType::Data { length: 1, channel }
+    }
+
+    #[test]
+    fn latencies_per_window_and_run() {
+        let topology = Topology::default();
+        let mut latency = RemoteLatency::new(Duration::from_nanos(100));
+        latency.update(&edge((0, 0), (10, 1), data(3)));
+        latency.update(&edge((20, 1), (50, 0), EdgeType::Progress));
+        latency.update(&edge((90, 0), (130, 1), data(3)));
+        // local edges aren't messages
+        latency.update(&edge((0, 0), (50, 0), processing(1)));
+
+        assert!(latency.advance(Duration::from_nanos(99), &topology).is_empty());
+
+        let reports = latency.advance(Duration::from_nanos(100), &topology);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].window, Some(Duration::default()));
+        assert_eq!(reports[0].pairs.iter().map(|p| (p.kind, p.sender, p.receiver, p.percentiles.max)).collect::<Vec<_>>(), vec![
+            (EdgeKind::Progress, 1, 0, Duration::from_nanos(30)),
+            (EdgeKind::Data, 0, 1, Duration::from_nanos(10)),
+        ]);
+        assert_eq!(reports[0].channels.iter().map(|c| (c.channel, c.percentiles.count)).collect::<Vec<_>>(), vec![(3, 1)]);
+
+        // the message received in the second window counts towards it, not the one it was sent in
+        let reports = latency.finish(&topology);
+        assert_eq!(reports.iter().map(|r| r.window).collect::<Vec<_>>(), vec![Some(Duration::from_nanos(100)), None]);
+        assert_eq!(reports[0].channels[0].percentiles.max, Duration::from_nanos(40));
+        assert_eq!(reports[1].channels[0].percentiles.count, 2);
+        assert_eq!(reports[1].pairs.iter().map(|p| p.percentiles.count).sum::<u64>(), 3);
+    }
+
+    #[test]
+    fn skewed_messages_count_as_zero_latency() {
+        let topology = Topology::default();
+        let mut latency = RemoteLatency::new(Duration::from_nanos(100));
+        // received 30ns before it was sent, as worker 1's clock is behind
+        latency.update(&edge((120, 0), (90, 1), data(3)));
+        latency.update(&edge((10, 0), (30, 1), data(3)));
+
+        // it's complete only once it was sent, so it counts towards the window it was sent in
+        let reports = latency.advance(Duration::from_nanos(100), &topology);
+        assert_eq!(reports[0].pairs[0].percentiles.max, Duration::from_nanos(20));
+        assert_eq!(reports[0].pairs[0].skewed, 0);
+
+        let reports = latency.finish(&topology);
+        assert_eq!(reports[0].window, Some(Duration::from_nanos(100)));
+        assert_eq!((reports[0].pairs[0].percentiles.max, reports[0].pairs[0].skewed), (Duration::default(), 1));
+        let total = &reports[1].pairs[0];
+        assert_eq!((total.percentiles.count, total.percentiles.p50, total.skewed), (2, Duration::default(), 1));
+    }
+
+    #[test]
+    fn reports_each_message_once() {
+        let pag = build_pag(exchange_events(2, 10), true);
+        let reports = run_incrementally(RemoteLatency::new(Duration::from_micros(10)), &pag, Duration::from_micros(3), &Topology::default());
+
+        let (total, windows) = reports.split_last().unwrap();
+        assert_eq!(total.window, None);
+        let count = |report: &LatencyReport| report.pairs.iter().map(|p| p.percentiles.count).sum::<u64>();
+        assert_eq!(windows.iter().map(count).sum::<u64>(), count(total));
+        assert_eq!(count(total) as usize, pag.iter().filter(|e| e.src.wid != e.dst.wid).count());
+    }
+}
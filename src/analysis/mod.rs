@@ -0,0 +1,164 @@
+//! Analyses on top of a constructed PAG.
+//! Analyses consume the PAG incrementally and report their results as soon
+//! as they are final, cf. `Analysis` and `Analyze`.
+
+#![deny(missing_docs)]
+
+use std::time::Duration;
+
+use timely::Data;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::generic::operator::Operator;
+
+use crate::{Event, PagEdge};
+use crate::topology::Topology;
+
+pub mod summary;
+pub mod scopes;
+pub mod waits;
+pub mod histogram;
+pub mod processing;
+pub mod latency;
+pub mod outliers;
+pub mod scheduling;
+pub mod progress;
+pub mod parallelism;
+pub mod utilization;
+pub mod critical;
+pub mod whatif;
+
+/// Start of the fixed-size window `t` falls into
+pub(crate) fn window_start(t: Duration, window: Duration) -> Duration {
+    let window = window.as_nanos().max(1);
+    let start = t.as_nanos() / window * window;
+    Duration::from_nanos(start as u64)
+}
+
+/// An analysis that consumes the PAG edge by edge.
+/// `advance` and `finish` receive the topology reconstructed from all events so far,
+/// e.g. to name operators or to resolve channel endpoints in results.
+/// Analyses that aggregate over the whole run don't report anything before `finish`.
+pub trait Analysis {
+    /// A single result, e.g. of a window
+    type Output;
+
+    /// Incorporates a PAG edge. Edges arrive in no particular order.
+    fn update(&mut self, edge: &PagEdge);
+
+    /// Incorporates a log event, for analyses that aren't based on the PAG alone.
+    fn update_event(&mut self, _event: &Event) {}
+
+    /// Returns the results that have become final, given that all local edges
+    /// starting before `until`, all remote edges both sent and received before
+    /// `until` and all events before `until` have been incorporated.
+    fn advance(&mut self, until: Duration, topology: &Topology) -> Vec<Self::Output>;
+
+    /// Returns all remaining results once the PAG is complete.
+    fn finish(&mut self, topology: &Topology) -> Vec<Self::Output>;
+}
+
+/// Runs analyses on a stream of PAG edges, e.g. as constructed by `PagBuilder`.
+/// Edges are analyzed where they are, so they usually have to be exchanged to
+/// a single worker first.
+pub trait Analyze<S: Scope<Timestamp = Duration>> {
+    /// Runs `analysis` on the PAG, given a stream of `events` that contains at least
+    /// the `Operates`, `Channels` and `Shutdown` events to reconstruct the topology.
+    /// Results are emitted once the frontier of both inputs has passed them.
+    fn analyze<A>(&self, events: &Stream<S, Event>, analysis: A) -> Stream<S, A::Output>
+    where A: Analysis + 'static, A::Output: Data;
+}
+
+impl<S: Scope<Timestamp = Duration>> Analyze<S> for Stream<S, PagEdge> {
+    fn analyze<A>(&self, events: &Stream<S, Event>, mut analysis: A) -> Stream<S, A::Output>
+    where A: Analysis + 'static, A::Output: Data
+    {
+        self.binary_frontier(events, Pipeline, Pipeline, "Analyze", move |cap, _| {
+            let mut cap = Some(cap);
+            let mut topology = Topology::default();
+            let mut until = Duration::default();
+
+            let mut edges_vector = Vec::new();
+            let mut events_vector = Vec::new();
+
+            move |edges, events, output| {
+                events.for_each(|_, data| {
+                    data.swap(&mut events_vector);
+                    for event in events_vector.drain(..) {
+                        topology.update(&event);
+                        analysis.update_event(&event);
+                    }
+                });
+
+                edges.for_each(|_, data| {
+                    data.swap(&mut edges_vector);
+                    for edge in edges_vector.drain(..) {
+                        analysis.update(&edge);
+                    }
+                });
+
+                let frontier = edges.frontier().frontier().iter()
+                    .chain(events.frontier().frontier().iter())
+                    .min()
+                    .copied();
+
+                match frontier {
+                    Some(frontier) if frontier > until => if let Some(cap) = cap.as_mut() {
+                        until = frontier;
+                        output.session(cap).give_iterator(analysis.advance(until, &topology).into_iter());
+                        cap.downgrade(&until);
+                    },
+                    None => if let Some(cap) = cap.take() {
+                        output.session(&cap).give_iterator(analysis.finish(&topology).into_iter());
+                    },
+                    _ => {}
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{dataflow_analysis, exchange_events};
+
+    /// Records edges that arrive although `advance` claimed they were complete
+    #[derive(Default)]
+    struct Late {
+        until: Duration,
+        advances: usize,
+        late: Vec<PagEdge>,
+    }
+
+    impl Analysis for Late {
+        type Output = (usize, Vec<PagEdge>);
+
+        fn update(&mut self, edge: &PagEdge) {
+            let complete = if edge.src.wid == edge.dst.wid { edge.src.t } else { edge.src.t.max(edge.dst.t) };
+            if complete < self.until {
+                self.late.push(edge.clone());
+            }
+        }
+
+        fn advance(&mut self, until: Duration, _topology: &Topology) -> Vec<Self::Output> {
+            self.until = until;
+            self.advances += 1;
+            Vec::new()
+        }
+
+        fn finish(&mut self, _topology: &Topology) -> Vec<Self::Output> {
+            vec![(self.advances, std::mem::take(&mut self.late))]
+        }
+    }
+
+    #[test]
+    fn edges_complete_before_frontier() {
+        let results = dataflow_analysis(exchange_events(2, 10), Late::default());
+        assert_eq!(results.len(), 1);
+
+        let (advances, late) = &results[0];
+        assert!(*advances > 1);
+        assert!(late.is_empty(), "late edges: {:?}", late);
+    }
+}
@@ -0,0 +1,312 @@
+//! Points at the individual edges responsible for a slow window: the longest
+//! edges per window and kind, and edges that take unusually long compared to
+//! earlier activity of the same operator.
+
+#![deny(missing_docs)]
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeKind};
+use crate::analysis::{Analysis, window_start};
+use crate::topology::Topology;
+
+/// Edges whose duration deviates by more standard deviations than this
+/// from their operator's history are flagged as outliers
+const OUTLIER_ZSCORE: f64 = 3.0;
+/// Minimum #earlier edges of an operator before its edges are judged
+const MIN_HISTORY: u64 = 10;
+
+/// An edge worth looking at, in the context of its surroundings
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct NotableEdge {
+    /// Start of the window the edge starts in
+    pub window: Duration,
+    /// Kind of the edge
+    pub kind: EdgeKind,
+    /// The edge itself
+    pub edge: PagEdge,
+    /// Duration of the edge
+    pub duration: Duration,
+    /// Name of the operator the edge is attributed to, if any
+    pub name: Option<String>,
+    /// Edges ending where this edge starts, local and remote
+    pub before: Vec<PagEdge>,
+    /// Edges starting where this edge ends, local and remote
+    pub after: Vec<PagEdge>,
+    /// Deviation from the mean duration of earlier edges of the same kind
+    /// and operator, in standard deviations (if there's enough history)
+    pub zscore: Option<f64>,
+}
+
+/// Longest edges and outliers of a window
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct OutlierReport {
+    /// Start of the window
+    pub window: Duration,
+    /// The `k` longest edges per kind, longest first
+    pub top: Vec<NotableEdge>,
+    /// Edges taking more than `OUTLIER_ZSCORE` standard deviations longer than their operator's history
+    pub outliers: Vec<NotableEdge>,
+}
+
+/// min-heap of the longest (duration, edge sequence number) pairs seen so far
+type TopEdges = BinaryHeap<Reverse<(Duration, usize)>>;
+
+/// Running mean and variance of durations (Welford's algorithm)
+#[derive(Clone, Copy, Default, Debug)]
+struct History {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl History {
+    fn record(&mut self, nanos: f64) {
+        self.count += 1;
+        let delta = nanos - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (nanos - self.mean);
+    }
+
+    fn zscore(&self, nanos: f64) -> Option<f64> {
+        if self.count < MIN_HISTORY {
+            return None;
+        }
+        let stddev = (self.m2 / self.count as f64).sqrt();
+        if stddev > 0.0 {
+            Some((nanos - self.mean) / stddev)
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds the `k` longest edges per window and kind, and flags edges attributed to an
+/// operator that are outliers compared to that operator's earlier edges of the same kind.
+pub fn find_outliers(pag: &[PagEdge], window: Duration, k: usize, topology: &Topology) -> Vec<OutlierReport> {
+    let mut outliers = Outliers::new(window, k);
+    pag.iter().for_each(|edge| outliers.update(edge));
+    outliers.finish(topology)
+}
+
+/// Notable edges of a window that hasn't been reported yet
+#[derive(Default)]
+struct WindowEdges {
+    /// longest edges by kind
+    top: BTreeMap<EdgeKind, TopEdges>,
+    /// sequence numbers of outliers
+    outliers: Vec<usize>,
+    /// (edge, zscore, whether it's an outlier) of all edges referenced above, by sequence number
+    edges: HashMap<usize, (PagEdge, Option<f64>, bool)>,
+}
+
+/// Incrementally finds notable edges, cf. `find_outliers`. Edges are judged in the
+/// order they start, once the PAG is complete up to their start. A window is
+/// reported once the PAG is complete up to a window past its end, and past the
+/// end of all its notable edges, s.t. their neighbors are known.
+pub struct Outliers {
+    window: Duration,
+    k: usize,
+    /// edges that haven't been judged yet
+    pending: Vec<PagEdge>,
+    history: HashMap<(EdgeKind, usize), History>,
+    windows: BTreeMap<Duration, WindowEdges>,
+    /// start of the first window that hasn't been reported
+    reported: Duration,
+    /// edges by their (source time, worker), to look up neighbors
+    by_src: BTreeMap<(Duration, usize), Vec<PagEdge>>,
+    /// edges by their (destination time, worker), to look up neighbors
+    by_dst: BTreeMap<(Duration, usize), Vec<PagEdge>>,
+    /// sequence number of the next judged edge
+    seq: usize,
+}
+
+impl Outliers {
+    /// Creates an analysis finding the `k` longest edges per kind in windows of size `window`.
+    pub fn new(window: Duration, k: usize) -> Self {
+        Outliers {
+            window,
+            k,
+            pending: Vec::new(),
+            history: HashMap::new(),
+            windows: BTreeMap::new(),
+            reported: Duration::default(),
+            by_src: BTreeMap::new(),
+            by_dst: BTreeMap::new(),
+            seq: 0,
+        }
+    }
+
+    /// Judges the next edge (in order of their start) against its operator's history.
+    fn judge(&mut self, edge: PagEdge) {
+        let kind = edge.edge_type.kind();
+        let duration = edge.duration();
+
+        let zscore = edge.edge_type.oid().and_then(|oid| {
+            let nanos = duration.as_nanos() as f64;
+            let history = self.history.entry((kind, oid)).or_default();
+            let zscore = history.zscore(nanos);
+            history.record(nanos);
+            zscore
+        });
+
+        self.by_src.entry((edge.src.t, edge.src.wid)).or_default().push(edge.clone());
+        self.by_dst.entry((edge.dst.t, edge.dst.wid)).or_default().push(edge.clone());
+
+        // remote edges can arrive after their window has been reported
+        let window = window_start(edge.src.t, self.window);
+        if window < self.reported {
+            return;
+        }
+
+        let seq = self.seq;
+        self.seq += 1;
+
+        let edges = self.windows.entry(window).or_default();
+        let outlier = zscore.is_some_and(|zscore| zscore > OUTLIER_ZSCORE);
+        if outlier {
+            edges.outliers.push(seq);
+        }
+        edges.edges.insert(seq, (edge, zscore, outlier));
+
+        let heap = edges.top.entry(kind).or_default();
+        heap.push(Reverse((duration, seq)));
+        if heap.len() > self.k {
+            if let Some(Reverse((_, seq))) = heap.pop() {
+                if !edges.edges[&seq].2 {
+                    edges.edges.remove(&seq);
+                }
+            }
+        }
+    }
+
+    /// Judges all pending edges starting before `until`, in the order they start.
+    fn judge_pending(&mut self, until: Duration) {
+        let (mut ready, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending).into_iter()
+            .partition(|e| e.src.t < until);
+        self.pending = pending;
+
+        ready.sort_by_key(|e| e.src.t);
+        for edge in ready {
+            self.judge(edge);
+        }
+    }
+
+    fn report(&self, window: Duration, edges: WindowEdges, topology: &Topology) -> OutlierReport {
+        let notable = |seq: &usize| {
+            let (edge, zscore, _) = &edges.edges[seq];
+            NotableEdge {
+                window,
+                kind: edge.edge_type.kind(),
+                edge: edge.clone(),
+                duration: edge.duration(),
+                name: edge.edge_type.oid().and_then(|oid| topology.operators.get(&oid)).map(|op| op.name.clone()),
+                before: self.by_dst.get(&(edge.src.t, edge.src.wid)).cloned().unwrap_or_default(),
+                after: self.by_src.get(&(edge.dst.t, edge.dst.wid)).cloned().unwrap_or_default(),
+                zscore: *zscore,
+            }
+        };
+
+        OutlierReport {
+            window,
+            top: edges.top.values()
+                .flat_map(|heap| heap.clone().into_sorted_vec().into_iter().map(|Reverse((_, seq))| seq))
+                .map(|seq| notable(&seq))
+                .collect(),
+            outliers: edges.outliers.iter().map(notable).collect(),
+        }
+    }
+}
+
+impl Analysis for Outliers {
+    type Output = OutlierReport;
+
+    fn update(&mut self, edge: &PagEdge) {
+        self.pending.push(edge.clone());
+    }
+
+    fn advance(&mut self, until: Duration, topology: &Topology) -> Vec<OutlierReport> {
+        self.judge_pending(until);
+
+        let mut reports = Vec::new();
+        while let Some(entry) = self.windows.first_entry() {
+            let window = *entry.key();
+            let end = entry.get().edges.values().map(|(e, ..)| e.dst.t).fold(window + self.window, Duration::max);
+            if until < end + self.window {
+                break;
+            }
+
+            let edges = entry.remove();
+            reports.push(self.report(window, edges, topology));
+            self.reported = window + self.window;
+        }
+
+        // neighbors of edges in reported windows aren't looked up anymore
+        self.by_src = self.by_src.split_off(&(self.reported, 0));
+        self.by_dst = self.by_dst.split_off(&(self.reported, 0));
+
+        reports
+    }
+
+    fn finish(&mut self, topology: &Topology) -> Vec<OutlierReport> {
+        self.judge_pending(Duration::MAX);
+        let windows = std::mem::take(&mut self.windows);
+        windows.into_iter()
+            .map(|(window, edges)| self.report(window, edges, topology))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EdgeType;
+    use crate::testing::{edge, processing};
+
+    #[test]
+    fn longest_edges_with_neighbors() {
+        let topology = Topology::default();
+        let mut outliers = Outliers::new(Duration::from_nanos(100), 1);
+        let (first, busy, second, next) = (
+            edge((0, 0), (10, 0), processing(1)),
+            edge((10, 0), (40, 0), EdgeType::Busy),
+            edge((40, 0), (60, 0), processing(1)),
+            edge((150, 0), (160, 0), processing(1)),
+        );
+        for edge in [&next, &second, &busy, &first] {
+            outliers.update(edge);
+        }
+
+        // neighbors of edges in the next window may still be missing
+        assert!(outliers.advance(Duration::from_nanos(199), &topology).is_empty());
+
+        let reports = outliers.advance(Duration::from_nanos(200), &topology);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].window, Duration::default());
+        assert_eq!(reports[0].top.iter().map(|n| n.edge.clone()).collect::<Vec<_>>(), vec![second.clone(), busy.clone()]);
+        assert_eq!(reports[0].top[1].before, vec![first]);
+        assert_eq!(reports[0].top[1].after, vec![second]);
+
+        let reports = outliers.finish(&topology);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].top[0].edge, next);
+    }
+
+    #[test]
+    fn flags_outliers_against_history() {
+        let mut pag: Vec<PagEdge> = (0 .. 2 * MIN_HISTORY)
+            .map(|i| edge((10 * i, 0), (10 * i + 1 + 2 * (i % 2), 0), processing(1)))
+            .collect();
+        pag.push(edge((1000, 0), (1020, 0), processing(1)));
+
+        let reports = find_outliers(&pag, Duration::from_secs(1), 1, &Topology::default());
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].outliers.len(), 1);
+        assert_eq!(reports[0].outliers[0].edge, pag[pag.len() - 1]);
+        assert_eq!(reports[0].outliers[0].zscore, Some(18.0));
+    }
+}
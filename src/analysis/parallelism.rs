@@ -0,0 +1,241 @@
+//! Effective parallelism over time: how many workers are processing, waiting
+//! or spinning at each instant, derived from overlapping local edges.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeKind};
+use crate::analysis::{Analysis, window_start};
+use crate::topology::Topology;
+
+/// #workers per activity from `t` on, until the next step
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ParallelismStep {
+    /// Time of the change
+    pub t: Duration,
+    /// #workers processing, including handling messages and notifications
+    pub processing: usize,
+    /// #workers waiting, regardless of the cause
+    pub waiting: usize,
+    /// #workers spinning
+    pub spinning: usize,
+}
+
+impl ParallelismStep {
+    /// Header of the CSV export of steps
+    pub const CSV_HEADER: &'static str = "t_ns,processing,waiting,spinning";
+
+    /// Exports the step as a CSV row.
+    pub fn to_csv(&self) -> String {
+        format!("{},{},{},{}", self.t.as_nanos(), self.processing, self.waiting, self.spinning)
+    }
+}
+
+/// Time-weighted average #workers per activity within a window
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ParallelismWindow {
+    /// Start of the window
+    pub window: Duration,
+    /// average #workers processing
+    pub processing: f64,
+    /// average #workers waiting
+    pub waiting: f64,
+    /// average #workers spinning
+    pub spinning: f64,
+    /// maximum #workers processing at the same time
+    pub max_processing: usize,
+    /// Every change in #workers per activity within the window, ordered by time
+    pub steps: Vec<ParallelismStep>,
+}
+
+/// Computes the parallelism profile from the local edges of a PAG.
+pub fn parallelism(pag: &[PagEdge], window: Duration) -> Vec<ParallelismWindow> {
+    let mut profile = ParallelismProfile::new(window);
+    pag.iter().for_each(|edge| profile.update(edge));
+    profile.finish(&Topology::default())
+}
+
+/// Window being integrated
+#[derive(Default)]
+struct WindowState {
+    /// covered time in ns
+    covered: f64,
+    /// #workers per activity, integrated over time in ns
+    integrated: [f64; 3],
+    max_processing: usize,
+    steps: Vec<ParallelismStep>,
+}
+
+/// Incrementally computes the parallelism profile, cf. `parallelism`.
+/// Changes are swept once the PAG is complete up to them, and a window is
+/// reported once the sweep has passed its end. Windows are averaged over the
+/// part of them covered by the PAG.
+pub struct ParallelismProfile {
+    window: Duration,
+    /// changes in (processing, waiting, spinning) by time, not yet swept
+    changes: BTreeMap<Duration, [isize; 3]>,
+    /// #workers per activity since the last swept change
+    current: [isize; 3],
+    /// time of the last swept change
+    last: Option<Duration>,
+    windows: BTreeMap<Duration, WindowState>,
+}
+
+impl ParallelismProfile {
+    /// Creates an empty profile with windows of size `window`.
+    pub fn new(window: Duration) -> Self {
+        ParallelismProfile {
+            window,
+            changes: BTreeMap::new(),
+            current: [0; 3],
+            last: None,
+            windows: BTreeMap::new(),
+        }
+    }
+
+    /// Incorporates a PAG edge. Only local operator work, waiting and spinning edges count.
+    pub fn update(&mut self, edge: &PagEdge) {
+        if edge.src.wid != edge.dst.wid {
+            return;
+        }
+
+        let activity = match edge.edge_type.kind() {
+            kind if kind.is_operator_work() => 0,
+            kind if kind.is_waiting() => 1,
+            EdgeKind::Spinning => 2,
+            _ => return,
+        };
+        self.changes.entry(edge.src.t).or_default()[activity] += 1;
+        self.changes.entry(edge.dst.t).or_default()[activity] -= 1;
+    }
+
+    /// Sweeps all changes before `until`.
+    fn sweep(&mut self, until: Duration) {
+        let rest = self.changes.split_off(&until);
+        for (t, delta) in std::mem::replace(&mut self.changes, rest) {
+            if let Some(mut start) = self.last {
+                // split the interval since the previous change at window boundaries
+                while start < t {
+                    let w = window_start(start, self.window);
+                    let end = std::cmp::min(t, w + self.window);
+                    let nanos = (end - start).as_nanos() as f64;
+
+                    let entry = self.windows.entry(w).or_default();
+                    entry.covered += nanos;
+                    for (integrated, c) in entry.integrated.iter_mut().zip(self.current.iter()) {
+                        *integrated += *c as f64 * nanos;
+                    }
+                    entry.max_processing = std::cmp::max(entry.max_processing, self.current[0] as usize);
+
+                    start = end;
+                }
+            }
+
+            let before = self.current;
+            for (c, d) in self.current.iter_mut().zip(delta.iter()) {
+                *c += d;
+            }
+            // edges ending where others start don't change anything
+            if self.last.is_none() || before != self.current {
+                self.windows.entry(window_start(t, self.window)).or_default().steps.push(ParallelismStep {
+                    t,
+                    processing: self.current[0] as usize,
+                    waiting: self.current[1] as usize,
+                    spinning: self.current[2] as usize,
+                });
+            }
+            self.last = Some(t);
+        }
+    }
+
+    fn report(windows: BTreeMap<Duration, WindowState>) -> Vec<ParallelismWindow> {
+        let average = |integrated: f64, covered: f64| if covered > 0.0 { integrated / covered } else { 0.0 };
+        windows.into_iter()
+            .map(|(window, state)| ParallelismWindow {
+                window,
+                processing: average(state.integrated[0], state.covered),
+                waiting: average(state.integrated[1], state.covered),
+                spinning: average(state.integrated[2], state.covered),
+                max_processing: state.max_processing,
+                steps: state.steps,
+            })
+            .collect()
+    }
+}
+
+impl Analysis for ParallelismProfile {
+    type Output = ParallelismWindow;
+
+    fn update(&mut self, edge: &PagEdge) {
+        ParallelismProfile::update(self, edge);
+    }
+
+    fn advance(&mut self, until: Duration, _topology: &Topology) -> Vec<ParallelismWindow> {
+        self.sweep(until);
+        match self.last {
+            Some(last) => {
+                let open = self.windows.split_off(&window_start(last, self.window));
+                ParallelismProfile::report(std::mem::replace(&mut self.windows, open))
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn finish(&mut self, _topology: &Topology) -> Vec<ParallelismWindow> {
+        self.sweep(Duration::MAX);
+        ParallelismProfile::report(std::mem::take(&mut self.windows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EdgeType;
+    use crate::testing::{edge, exchange_events, run_incrementally, processing};
+    use crate::offline::build_pag;
+
+    fn step(t: u64, processing: usize, waiting: usize) -> ParallelismStep {
+        ParallelismStep { t: Duration::from_nanos(t), processing, waiting, spinning: 0 }
+    }
+
+    #[test]
+    fn reports_windows_once_swept() {
+        let topology = Topology::default();
+        let mut profile = ParallelismProfile::new(Duration::from_nanos(100));
+        profile.update(&edge((0, 0), (50, 0), processing(1)));
+        profile.update(&edge((20, 1), (120, 1), processing(1)));
+        profile.update(&edge((120, 1), (150, 1), EdgeType::Waiting(crate::WaitingCause::Data)));
+
+        assert!(profile.advance(Duration::from_nanos(60), &topology).is_empty());
+        assert_eq!(profile.advance(Duration::from_nanos(130), &topology), vec![ParallelismWindow {
+            window: Duration::default(),
+            processing: 1.3,
+            waiting: 0.0,
+            spinning: 0.0,
+            max_processing: 2,
+            steps: vec![step(0, 1, 0), step(20, 2, 0), step(50, 1, 0)],
+        }]);
+        assert_eq!(profile.finish(&topology), vec![ParallelismWindow {
+            window: Duration::from_nanos(100),
+            processing: 0.4,
+            waiting: 0.6,
+            spinning: 0.0,
+            max_processing: 1,
+            steps: vec![step(120, 0, 1), step(150, 0, 0)],
+        }]);
+    }
+
+    #[test]
+    fn sweeps_incrementally_as_in_batch() {
+        let pag = build_pag(exchange_events(2, 10), true);
+        let window = Duration::from_micros(10);
+
+        let incremental = run_incrementally(ParallelismProfile::new(window), &pag, Duration::from_micros(3), &Topology::default());
+        assert!(!incremental.is_empty());
+        assert_eq!(incremental, parallelism(&pag, window));
+    }
+}
@@ -0,0 +1,185 @@
+//! Distribution of processing durations per operator and per worker,
+//! s.t. tail latencies aren't hidden behind averages.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::PagEdge;
+use crate::analysis::{Analysis, window_start};
+use crate::analysis::histogram::{Histogram, Percentiles};
+use crate::topology::Topology;
+
+/// Processing percentiles of an operator
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct OperatorProcessing {
+    /// Start of the window, `None` for the whole run
+    pub window: Option<Duration>,
+    /// Operator ID
+    pub oid: usize,
+    /// Operator name, if known
+    pub name: Option<String>,
+    /// Percentiles of its processing edges' durations
+    pub percentiles: Percentiles,
+}
+
+/// Processing percentiles of a worker, across all its operators
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct WorkerProcessing {
+    /// Start of the window, `None` for the whole run
+    pub window: Option<Duration>,
+    /// Worker ID
+    pub wid: usize,
+    /// Percentiles of its processing edges' durations
+    pub percentiles: Percentiles,
+}
+
+/// Processing percentiles of a window, or of the whole run
+#[derive(Clone, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ProcessingReport {
+    /// Start of the window, `None` for the whole run
+    pub window: Option<Duration>,
+    /// Percentiles by operator
+    pub operators: Vec<OperatorProcessing>,
+    /// Percentiles by worker
+    pub workers: Vec<WorkerProcessing>,
+}
+
+/// Maintains histograms of operator work durations (cf. `EdgeKind::is_operator_work`)
+/// by operator and by worker. Edges are assigned to the window they start in;
+/// histograms of a window are dropped once it is reported.
+#[derive(Clone, Debug)]
+pub struct ProcessingHistograms {
+    window: Duration,
+    /// histograms by (window, operator ID)
+    operators: BTreeMap<(Duration, usize), Histogram>,
+    /// histograms by (window, worker ID)
+    workers: BTreeMap<(Duration, usize), Histogram>,
+    /// histograms of the whole run by operator ID
+    total_operators: BTreeMap<usize, Histogram>,
+    /// histograms of the whole run by worker ID
+    total_workers: BTreeMap<usize, Histogram>,
+}
+
+impl ProcessingHistograms {
+    /// Creates empty histograms for windows of size `window`.
+    pub fn new(window: Duration) -> Self {
+        ProcessingHistograms {
+            window,
+            operators: BTreeMap::new(),
+            workers: BTreeMap::new(),
+            total_operators: BTreeMap::new(),
+            total_workers: BTreeMap::new(),
+        }
+    }
+
+    /// Incorporates a PAG edge. Only operator work is recorded; edges
+    /// without an operator only count towards their worker.
+    pub fn update(&mut self, edge: &PagEdge) {
+        if edge.edge_type.kind().is_operator_work() {
+            let window = window_start(edge.src.t, self.window);
+            let duration = edge.duration();
+
+            if let Some(oid) = edge.edge_type.oid() {
+                self.operators.entry((window, oid)).or_default().record(duration);
+                self.total_operators.entry(oid).or_default().record(duration);
+            }
+            self.workers.entry((window, edge.src.wid)).or_default().record(duration);
+            self.total_workers.entry(edge.src.wid).or_default().record(duration);
+        }
+    }
+
+    /// Percentiles of all windows starting before `open`, which are dropped.
+    fn report_windows(&mut self, open: Duration, topology: &Topology) -> Vec<ProcessingReport> {
+        let remaining = self.operators.split_off(&(open, 0));
+        let operators = std::mem::replace(&mut self.operators, remaining);
+        let remaining = self.workers.split_off(&(open, 0));
+        let workers = std::mem::replace(&mut self.workers, remaining);
+
+        let mut reports: BTreeMap<Duration, ProcessingReport> = BTreeMap::new();
+        for ((window, oid), h) in operators {
+            reports.entry(window).or_insert_with(|| report(window)).operators
+                .push(OperatorProcessing { window: Some(window), oid, name: name(topology, oid), percentiles: h.percentiles() });
+        }
+        for ((window, wid), h) in workers {
+            reports.entry(window).or_insert_with(|| report(window)).workers
+                .push(WorkerProcessing { window: Some(window), wid, percentiles: h.percentiles() });
+        }
+
+        reports.into_values().collect()
+    }
+}
+
+impl Analysis for ProcessingHistograms {
+    type Output = ProcessingReport;
+
+    fn update(&mut self, edge: &PagEdge) {
+        ProcessingHistograms::update(self, edge);
+    }
+
+    /// Reports every window that ended by `until`.
+    fn advance(&mut self, until: Duration, topology: &Topology) -> Vec<ProcessingReport> {
+        self.report_windows(window_start(until, self.window), topology)
+    }
+
+    /// Reports the remaining windows, followed by percentiles for the whole run.
+    fn finish(&mut self, topology: &Topology) -> Vec<ProcessingReport> {
+        let mut reports = self.report_windows(Duration::MAX, topology);
+        reports.push(ProcessingReport {
+            window: None,
+            operators: self.total_operators.iter()
+                .map(|(oid, h)| OperatorProcessing { window: None, oid: *oid, name: name(topology, *oid), percentiles: h.percentiles() })
+                .collect(),
+            workers: self.total_workers.iter()
+                .map(|(wid, h)| WorkerProcessing { window: None, wid: *wid, percentiles: h.percentiles() })
+                .collect(),
+        });
+        reports
+    }
+}
+
+/// An empty report of `window`
+fn report(window: Duration) -> ProcessingReport {
+    ProcessingReport { window: Some(window), ..Default::default() }
+}
+
+/// Name of an operator, if known
+fn name(topology: &Topology, oid: usize) -> Option<String> {
+    topology.operators.get(&oid).map(|op| op.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EdgeType;
+    use crate::testing::{edge, processing};
+
+    #[test]
+    fn percentiles_per_window_and_run() {
+        let topology = Topology::default();
+        let mut histograms = ProcessingHistograms::new(Duration::from_nanos(100));
+        histograms.update(&edge((0, 0), (10, 0), processing(1)));
+        histograms.update(&edge((50, 1), (70, 1), EdgeType::Processing { oid: None, send: None, recv: None }));
+        histograms.update(&edge((120, 0), (150, 0), processing(1)));
+        // not operator work
+        histograms.update(&edge((10, 0), (50, 0), EdgeType::Spinning(1)));
+
+        assert!(histograms.advance(Duration::from_nanos(99), &topology).is_empty());
+
+        let reports = histograms.advance(Duration::from_nanos(100), &topology);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].window, Some(Duration::default()));
+        assert_eq!(reports[0].operators.len(), 1);
+        assert_eq!(reports[0].operators[0].percentiles.max, Duration::from_nanos(10));
+        assert_eq!(reports[0].workers.iter().map(|w| w.wid).collect::<Vec<_>>(), vec![0, 1]);
+
+        let reports = histograms.finish(&topology);
+        assert_eq!(reports.iter().map(|r| r.window).collect::<Vec<_>>(), vec![Some(Duration::from_nanos(100)), None]);
+        let total = &reports[1].operators[0].percentiles;
+        assert_eq!((total.count, total.max), (2, Duration::from_nanos(30)));
+        assert!(histograms.operators.is_empty() && histograms.workers.is_empty());
+    }
+}
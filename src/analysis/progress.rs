@@ -0,0 +1,172 @@
+//! Overhead of the progress protocol per window: how many progress messages
+//! are exchanged, and how much time is spent on coordination,
+//! to tell when the protocol itself dominates the computation.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use timely::logging::TimelyEvent::Progress;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{Event, PagEdge, EdgeType, WaitingCause};
+use crate::analysis::{Analysis, window_start};
+use crate::topology::Topology;
+
+/// Progress protocol activity within a window
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProgressWindow {
+    /// Start of the window
+    pub window: Duration,
+    /// #progress messages sent
+    pub messages: usize,
+    /// total send → receive latency of remote progress edges
+    pub latency: Duration,
+    /// total time workers spent waiting on progress
+    pub waiting: Duration,
+    /// total time of all local activity
+    pub local: Duration,
+    /// fraction of local activity spent waiting on progress
+    pub share: f64,
+}
+
+/// Collects progress messages from log events and progress-related edges from the PAG.
+/// Messages are counted from the log events, since the PAG only contains remote ones.
+/// Timely 0.10 logs progress messages without their updates, so they aren't counted.
+/// Messages and local edges are assigned to the window they start in, remote edges
+/// to the window they're received in (or sent in, if the workers' clocks are skewed),
+/// s.t. a window is complete once the PAG is complete up to its end.
+#[derive(Clone, Debug)]
+pub struct ProgressOverhead {
+    window: Duration,
+    windows: BTreeMap<Duration, ProgressWindow>,
+}
+
+impl ProgressOverhead {
+    /// Creates an empty report for windows of size `window`.
+    pub fn new(window: Duration) -> Self {
+        ProgressOverhead {
+            window,
+            windows: BTreeMap::new(),
+        }
+    }
+
+    fn entry(&mut self, t: Duration) -> &mut ProgressWindow {
+        let window = window_start(t, self.window);
+        self.windows.entry(window).or_insert_with(|| ProgressWindow { window, ..Default::default() })
+    }
+
+    /// Incorporates a log event. Only sent `Progress` events are counted,
+    /// receives are already covered by the senders.
+    pub fn update_event(&mut self, event: &Event) {
+        let (t, _wid, x) = event;
+        if let Progress(e) = x {
+            if e.is_send {
+                self.entry(*t).messages += 1;
+            }
+        }
+    }
+
+    /// Incorporates a PAG edge.
+    pub fn update(&mut self, edge: &PagEdge) {
+        let remote = edge.src.wid != edge.dst.wid;
+        let duration = edge.duration();
+
+        let entry = self.entry(if remote { edge.src.t.max(edge.dst.t) } else { edge.src.t });
+        match edge.edge_type {
+            EdgeType::Progress if remote => entry.latency += duration,
+            EdgeType::Waiting(WaitingCause::Progress) => {
+                entry.waiting += duration;
+                entry.local += duration;
+            }
+            _ if !remote => entry.local += duration,
+            _ => {}
+        }
+    }
+
+    fn report_window(w: &ProgressWindow) -> ProgressWindow {
+        ProgressWindow {
+            share: if w.local > Duration::from_secs(0) {
+                w.waiting.as_secs_f64() / w.local.as_secs_f64()
+            } else {
+                0.0
+            },
+            ..w.clone()
+        }
+    }
+}
+
+impl Analysis for ProgressOverhead {
+    type Output = ProgressWindow;
+
+    fn update(&mut self, edge: &PagEdge) {
+        ProgressOverhead::update(self, edge);
+    }
+
+    fn update_event(&mut self, event: &Event) {
+        ProgressOverhead::update_event(self, event);
+    }
+
+    fn advance(&mut self, until: Duration, _topology: &Topology) -> Vec<ProgressWindow> {
+        let open = self.windows.split_off(&window_start(until, self.window));
+        std::mem::replace(&mut self.windows, open).values().map(ProgressOverhead::report_window).collect()
+    }
+
+    fn finish(&mut self, _topology: &Topology) -> Vec<ProgressWindow> {
+        std::mem::take(&mut self.windows).values().map(ProgressOverhead::report_window).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use timely::logging::ProgressEvent;
+    use crate::testing::edge;
+
+    fn progress(t: u64, is_send: bool) -> Event {
+        (Duration::from_nanos(t), 0, Progress(ProgressEvent {
+            is_send,
+            source: 0,
+            channel: 1,
+            seq_no: 0,
+            addr: vec![0],
+            messages: Vec::new(),
+            internal: Vec::new(),
+        }))
+    }
+
+    #[test]
+    fn counts_sent_messages() {
+        let mut overhead = ProgressOverhead::new(Duration::from_nanos(100));
+        overhead.update_event(&progress(10, true));
+        overhead.update_event(&progress(20, true));
+        overhead.update_event(&progress(30, false));
+        overhead.update_event(&progress(130, true));
+
+        let report = overhead.finish(&Topology::default());
+        assert_eq!(report.iter().map(|w| (w.window, w.messages)).collect::<Vec<_>>(), vec![
+            (Duration::default(), 2),
+            (Duration::from_nanos(100), 1),
+        ]);
+    }
+
+    #[test]
+    fn remote_edges_count_when_received() {
+        let topology = Topology::default();
+        let mut overhead = ProgressOverhead::new(Duration::from_nanos(100));
+        overhead.update(&edge((0, 0), (40, 0), EdgeType::Waiting(WaitingCause::Progress)));
+        overhead.update(&edge((40, 0), (60, 0), EdgeType::Busy));
+        overhead.update(&edge((90, 1), (110, 0), EdgeType::Progress));
+
+        let report = overhead.advance(Duration::from_nanos(150), &topology);
+        assert_eq!(report.len(), 1);
+        assert_eq!((report[0].waiting, report[0].local, report[0].latency), (Duration::from_nanos(40), Duration::from_nanos(60), Duration::default()));
+        assert!((report[0].share - 2.0 / 3.0).abs() < 1e-9);
+
+        let report = overhead.finish(&topology);
+        assert_eq!(report.len(), 1);
+        assert_eq!((report[0].window, report[0].latency), (Duration::from_nanos(100), Duration::from_nanos(20)));
+    }
+}
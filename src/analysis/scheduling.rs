@@ -0,0 +1,184 @@
+//! Scheduling overhead: operators that are scheduled, but stop again without
+//! doing any work (`Spinning`), compared to how often they actually process.
+
+#![deny(missing_docs)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeType};
+use crate::analysis::Analysis;
+use crate::topology::Topology;
+
+/// Operators spinning in at least this fraction of their activations are flagged
+const WASTEFUL_SPIN_RATIO: f64 = 0.9;
+/// Minimum #spins before an operator is flagged
+const MIN_SPINS: usize = 10;
+
+/// Scheduling overhead of an operator, summed over all workers
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct OperatorScheduling {
+    /// Operator ID
+    pub oid: usize,
+    /// Operator name, if known
+    pub name: Option<String>,
+    /// #activations without work
+    pub spins: usize,
+    /// total spinning time
+    pub spinning: Duration,
+    /// #activations with work
+    pub activations: usize,
+    /// total processing time
+    pub processing: Duration,
+    /// fraction of activations without work
+    pub spin_ratio: f64,
+    /// Whether the operator is activated far more often than it does work,
+    /// i.e. spins at least `MIN_SPINS` times in at least `WASTEFUL_SPIN_RATIO` of its activations
+    pub wasteful: bool,
+}
+
+/// Collects per-operator `Spinning` edges and activations with work.
+/// This relies on the untrimmed PAG: trimming merges busy time into spinning and
+/// processing edges, and consecutive spins of an operator into a single edge.
+/// Operator work is split into several edges e.g. by messages sent during an activation,
+/// so contiguous work edges of an operator on a worker count as a single activation.
+/// Messages sent right before an operator is scheduled (e.g. by an `InputHandle`) are
+/// attributed to that operator, so if it then spins, this doesn't count as an activation.
+#[derive(Clone, Default, Debug)]
+pub struct SchedulingProfile {
+    /// (spins, spinning time, activations, processing time) by operator ID
+    operators: BTreeMap<usize, (usize, Duration, usize, Duration)>,
+    /// work and spinning edges not yet known to follow work of their operator
+    pending: Vec<PagEdge>,
+    /// (worker, end, operator) of work edges that may still be continued
+    ends: BTreeSet<(usize, Duration, usize)>,
+}
+
+impl SchedulingProfile {
+    /// Incorporates a PAG edge. Only operator work and `Spinning` edges are attributed.
+    pub fn update(&mut self, edge: &PagEdge) {
+        match (edge.edge_type, edge.edge_type.oid()) {
+            (EdgeType::Spinning(oid), _) => {
+                let op = self.operators.entry(oid).or_default();
+                op.0 += 1;
+                op.1 += edge.duration();
+                self.pending.push(edge.clone());
+            }
+            (edge_type, Some(oid)) if edge_type.kind().is_operator_work() => {
+                self.operators.entry(oid).or_default().3 += edge.duration();
+                self.ends.insert((edge.dst.wid, edge.dst.t, oid));
+                self.pending.push(edge.clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Counts the activations started by pending edges. Since local edges arrive once
+    /// they started, the edge an activation would be continued from has arrived by then.
+    fn count_activations(&mut self, until: Duration) {
+        // work preceding a spin has to be counted before it
+        self.pending.sort_by_key(|e| e.src.t);
+        for edge in self.pending.drain(..) {
+            let oid = edge.edge_type.oid().expect("edge without operator");
+            let follows_work = self.ends.contains(&(edge.src.wid, edge.src.t, oid));
+            let op = self.operators.entry(oid).or_default();
+            match edge.edge_type {
+                EdgeType::Spinning(_) if follows_work => op.2 -= 1,
+                EdgeType::Spinning(_) => {}
+                _ if !follows_work => op.2 += 1,
+                _ => {}
+            }
+        }
+
+        // edges starting before `until` have all arrived
+        self.ends.retain(|(_, end, _)| *end >= until);
+    }
+
+    /// Scheduling overhead per operator, most spinning operators (by ratio) first.
+    pub fn report(&self, topology: &Topology) -> Vec<OperatorScheduling> {
+        let mut report: Vec<_> = self.operators.iter()
+            .map(|(oid, (spins, spinning, activations, processing))| {
+                let spin_ratio = *spins as f64 / (spins + activations) as f64;
+                OperatorScheduling {
+                    oid: *oid,
+                    name: topology.operators.get(oid).map(|op| op.name.clone()),
+                    spins: *spins,
+                    spinning: *spinning,
+                    activations: *activations,
+                    processing: *processing,
+                    spin_ratio,
+                    wasteful: *spins >= MIN_SPINS && spin_ratio >= WASTEFUL_SPIN_RATIO,
+                }
+            })
+            .collect();
+
+        report.sort_by(|a, b| b.spin_ratio.total_cmp(&a.spin_ratio).then(b.spins.cmp(&a.spins)));
+        report
+    }
+}
+
+impl Analysis for SchedulingProfile {
+    type Output = OperatorScheduling;
+
+    fn update(&mut self, edge: &PagEdge) {
+        SchedulingProfile::update(self, edge);
+    }
+
+    fn advance(&mut self, until: Duration, _topology: &Topology) -> Vec<OperatorScheduling> {
+        self.count_activations(until);
+        Vec::new()
+    }
+
+    fn finish(&mut self, topology: &Topology) -> Vec<OperatorScheduling> {
+        self.count_activations(Duration::MAX);
+        self.report(topology)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{edge, processing, exchange_events, dataflow_pag, run_incrementally};
+    use timely::logging::{StartStop, TimelyEvent};
+
+    #[test]
+    fn flags_operators_spinning_most_of_the_time() {
+        let mut pag = vec![
+            edge((0, 0), (5, 0), processing(1)),
+            // a single activation of operator 2, interrupted by a message it sent
+            edge((5, 0), (7, 0), processing(2)),
+            edge((7, 0), (10, 0), processing(2)),
+        ];
+        pag.extend((1 .. 2 * MIN_SPINS as u64).map(|i| edge((10 * i + 2, 0), (10 * i + 3, 0), EdgeType::Spinning(2))));
+
+        let report = run_incrementally(SchedulingProfile::default(), &pag, Duration::from_nanos(3), &Topology::default());
+        assert_eq!(report.iter().map(|op| (op.oid, op.wasteful)).collect::<Vec<_>>(), vec![(2, true), (1, false)]);
+        assert_eq!(report[0].spins, 2 * MIN_SPINS - 1);
+        assert_eq!(report[0].spinning, Duration::from_nanos(2 * MIN_SPINS as u64 - 1));
+        assert_eq!((report[0].activations, report[0].processing), (1, Duration::from_nanos(5)));
+    }
+
+    #[test]
+    fn counts_every_schedule_once() {
+        let events = exchange_events(2, 10);
+
+        // the last schedule of each worker doesn't complete an edge
+        let mut schedules: BTreeMap<usize, usize> = BTreeMap::new();
+        for (_, _, x) in events.iter() {
+            if let TimelyEvent::Schedule(e) = x {
+                if e.start_stop == StartStop::Start {
+                    *schedules.entry(e.id).or_default() += 1;
+                }
+            }
+        }
+
+        let pag = dataflow_pag(events, false);
+        let report = run_incrementally(SchedulingProfile::default(), &pag, Duration::from_micros(3), &Topology::default());
+        let counted: BTreeMap<usize, usize> = report.iter().map(|op| (op.oid, op.spins + op.activations)).collect();
+        for (oid, count) in counted.iter() {
+            assert!(*count <= schedules[oid] && schedules[oid] <= count + 2, "operator {}: {} scheduled, {} counted", oid, schedules[oid], count);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Scope-aware attribution: activity of leaf operators is rolled up into
+//! their enclosing scopes (e.g. `iterate`), s.t. the total time spent in a
+//! nested subcomputation is visible alongside its breakdown.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeType};
+use crate::analysis::Analysis;
+use crate::topology::Topology;
+
+/// Time attributed to an operator or scope
+#[derive(Clone, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ScopeTime {
+    /// Address of the operator or scope
+    pub addr: Vec<usize>,
+    /// Name of the operator or scope
+    pub name: String,
+    /// Processing time of all leaf operators at or below `addr`
+    pub processing: Duration,
+    /// Spinning time of all leaf operators at or below `addr`
+    pub spinning: Duration,
+    /// #leaf operators with activity at or below `addr`
+    pub operators: usize,
+}
+
+/// Collects per-operator activity from PAG edges. Since PAG construction
+/// peels off outer scopes, all activity is attributed to leaf operators;
+/// `rollup` restores the scope hierarchy using the topology.
+#[derive(Clone, Default, Debug)]
+pub struct ScopeProfile {
+    /// (processing, spinning) time by operator ID
+    operators: BTreeMap<usize, (Duration, Duration)>,
+}
+
+impl ScopeProfile {
+    /// Incorporates a PAG edge. Only operator work and `Spinning` edges are attributed.
+    pub fn update(&mut self, edge: &PagEdge) {
+        match (edge.edge_type, edge.edge_type.oid()) {
+            (EdgeType::Spinning(oid), _) => {
+                self.operators.entry(oid).or_default().1 += edge.duration();
+            }
+            (edge_type, Some(oid)) if edge_type.kind().is_operator_work() => {
+                self.operators.entry(oid).or_default().0 += edge.duration();
+            }
+            _ => {}
+        }
+    }
+
+    /// Rolls leaf activity up into all enclosing scopes.
+    /// Returns times for every operator and scope with activity, ordered by
+    /// address, i.e. every scope directly precedes its breakdown.
+    pub fn rollup(&self, topology: &Topology) -> Vec<ScopeTime> {
+        let mut scopes: BTreeMap<Vec<usize>, ScopeTime> = BTreeMap::new();
+
+        for (oid, (processing, spinning)) in self.operators.iter() {
+            let addr = match topology.operators.get(oid) {
+                Some(op) => &op.addr,
+                None => continue,
+            };
+
+            // the operator itself and all its prefixes
+            for len in 1 ..= addr.len() {
+                let prefix = &addr[.. len];
+                let scope = scopes.entry(prefix.to_vec()).or_insert_with(|| ScopeTime {
+                    addr: prefix.to_vec(),
+                    name: topology.operator_at(prefix).map(|op| op.name.clone()).unwrap_or_default(),
+                    ..Default::default()
+                });
+                scope.processing += *processing;
+                scope.spinning += *spinning;
+                scope.operators += 1;
+            }
+        }
+
+        scopes.into_values().collect()
+    }
+}
+
+impl Analysis for ScopeProfile {
+    type Output = ScopeTime;
+
+    fn update(&mut self, edge: &PagEdge) {
+        ScopeProfile::update(self, edge);
+    }
+
+    fn advance(&mut self, _until: Duration, _topology: &Topology) -> Vec<ScopeTime> {
+        Vec::new()
+    }
+
+    fn finish(&mut self, topology: &Topology) -> Vec<ScopeTime> {
+        self.rollup(topology)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline::build_pag;
+    use crate::testing::{log_events, run_incrementally};
+
+    use timely::dataflow::{InputHandle, Scope};
+    use timely::order::Product;
+    use timely::dataflow::operators::{Concat, Feedback, ConnectLoop, Filter, Input, Map, Probe, Enter, Leave};
+
+    /// Events of a computation with a loop nested in a loop
+    fn nested_events() -> Vec<crate::Event> {
+        log_events(1, |worker| {
+            let mut input = InputHandle::new();
+            let probe = worker.dataflow::<u64, _, _>(|scope| {
+                let stream = scope.input_from(&mut input);
+                scope.scoped::<Product<u64, u64>, _, _>("Outer", |outer| {
+                    let (handle, cycle) = outer.feedback(Product::new(0, 1));
+                    let stream = stream.enter(outer);
+                    let inner = outer.scoped::<Product<Product<u64, u64>, u64>, _, _>("Inner", |inner| {
+                        let (handle, cycle) = inner.feedback(Product::new(Default::default(), 1));
+                        let stream = stream.enter(inner)
+                            .concat(&cycle)
+                            .map(|x: u64| x / 2);
+                        stream.filter(|x| *x > 0).connect_loop(handle);
+                        stream.leave()
+                    });
+                    inner.concat(&cycle).filter(|x| *x > 100).connect_loop(handle);
+                    inner.leave()
+                }).probe()
+            });
+
+            for round in 0 .. 5 {
+                input.send(1 << round);
+                input.advance_to(round + 1);
+                while probe.less_than(input.time()) {
+                    worker.step();
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn rolls_up_nested_scopes() {
+        let events = nested_events();
+        let topology = Topology::from_events(events.iter());
+        let pag = build_pag(events, false);
+        let times = run_incrementally(ScopeProfile::default(), &pag, Duration::from_millis(1), &topology);
+
+        let outer = times.iter().find(|t| t.name == "Outer").expect("outer scope");
+        let inner = times.iter().find(|t| t.name == "Inner").expect("inner scope");
+        assert_eq!(inner.addr.len(), outer.addr.len() + 1);
+        assert!(inner.addr.starts_with(&outer.addr));
+        assert!(inner.processing > Duration::default());
+
+        // a scope's time is inclusive: its own operators' and that of nested scopes
+        for scope in times.iter().filter(|t| topology.operator_at(&t.addr).is_some()) {
+            let children = times.iter().filter(|t| t.addr.len() == scope.addr.len() + 1 && t.addr.starts_with(&scope.addr));
+            let (processing, spinning, operators) = children.fold((Duration::default(), Duration::default(), 0), |(p, s, o), t| (p + t.processing, s + t.spinning, o + t.operators));
+            if operators > 0 {
+                assert_eq!((scope.processing, scope.spinning, scope.operators), (processing, spinning, operators), "{:?}", scope);
+            }
+        }
+
+        // exclusively, i.e. at the leaves, every operator's time counts exactly once
+        let leaves: Vec<_> = times.iter().filter(|t| !times.iter().any(|c| c.addr.len() > t.addr.len() && c.addr.starts_with(&t.addr))).collect();
+        let dataflow = times.iter().find(|t| t.addr.len() == 1).unwrap();
+        assert_eq!(leaves.iter().map(|t| t.processing).sum::<Duration>(), dataflow.processing);
+        assert_eq!(leaves.len(), dataflow.operators);
+        assert!(leaves.iter().all(|t| t.operators == 1));
+        assert!(leaves.iter().any(|t| t.addr.starts_with(&inner.addr)));
+        assert!(leaves.iter().any(|t| t.addr.starts_with(&outer.addr) && !t.addr.starts_with(&inner.addr)));
+    }
+}
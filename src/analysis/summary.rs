@@ -0,0 +1,143 @@
+//! Aggregate time spent per activity type.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeKind};
+use crate::topology::Topology;
+
+/// Aggregate of all edges of an activity type
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ActivitySummary {
+    /// #edges
+    pub count: usize,
+    /// total duration of all edges
+    pub duration: Duration,
+}
+
+/// Aggregates a PAG by activity type.
+/// To summarize dataflows separately, use `DataflowSummaries`.
+#[derive(Clone, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Summary {
+    /// aggregates by activity type
+    pub activities: BTreeMap<EdgeKind, ActivitySummary>,
+}
+
+impl Summary {
+    /// Summarizes a sequence of PAG edges.
+    pub fn from_edges<'a, I: IntoIterator<Item = &'a PagEdge>>(edges: I) -> Self {
+        let mut summary = Summary::default();
+        for edge in edges {
+            summary.update(edge);
+        }
+        summary
+    }
+
+    /// Incorporates a PAG edge.
+    pub fn update(&mut self, edge: &PagEdge) {
+        let activity = self.activities.entry(edge.edge_type.kind()).or_default();
+        activity.count += 1;
+        activity.duration += edge.duration();
+    }
+
+    /// Adds all edges summarized in `other`.
+    pub fn merge(&mut self, other: &Summary) {
+        for (kind, activity) in other.activities.iter() {
+            let entry = self.activities.entry(*kind).or_default();
+            entry.count += activity.count;
+            entry.duration += activity.duration;
+        }
+    }
+}
+
+/// Summaries of a PAG spanning several dataflows, split by the dataflow each edge is
+/// attributed to (cf. `Topology::dataflow_of`). Workers interleave all their dataflows,
+/// so activity that isn't attributed to an operator or channel (waiting, busy, parked
+/// and progress) can't be assigned to a single dataflow; it is summarized as `shared`,
+/// along with activity of operators and channels missing from the topology.
+#[derive(Clone, Default, Debug)]
+pub struct DataflowSummaries {
+    all: Summary,
+    /// summaries by operator ID
+    operators: BTreeMap<usize, Summary>,
+    /// summaries by channel ID
+    channels: BTreeMap<usize, Summary>,
+    /// activity attributed to neither
+    shared: Summary,
+}
+
+impl DataflowSummaries {
+    /// Summarizes a sequence of PAG edges.
+    pub fn from_edges<'a, I: IntoIterator<Item = &'a PagEdge>>(edges: I) -> Self {
+        let mut summaries = DataflowSummaries::default();
+        for edge in edges {
+            summaries.update(edge);
+        }
+        summaries
+    }
+
+    /// Incorporates a PAG edge. Edges are resolved to dataflows only in `report`,
+    /// s.t. they may arrive before the topology knows their operator or channel.
+    pub fn update(&mut self, edge: &PagEdge) {
+        self.all.update(edge);
+        let summary = match (edge.edge_type.oid(), edge.edge_type.channel()) {
+            (Some(oid), _) => self.operators.entry(oid).or_default(),
+            (None, Some(channel)) => self.channels.entry(channel).or_default(),
+            (None, None) => &mut self.shared,
+        };
+        summary.update(edge);
+    }
+
+    /// Summaries of the whole PAG (`all`), of each dataflow (by its index),
+    /// and of activity shared by all dataflows (`shared`).
+    pub fn report(&self, topology: &Topology) -> BTreeMap<String, Summary> {
+        let mut report = BTreeMap::new();
+        report.insert("all".to_string(), self.all.clone());
+        report.insert("shared".to_string(), self.shared.clone());
+
+        let operators = self.operators.iter()
+            .map(|(oid, summary)| (topology.operators.get(oid).and_then(|op| op.addr.first().copied()), summary));
+        let channels = self.channels.iter()
+            .map(|(channel, summary)| (topology.channels.get(channel).and_then(|ch| ch.scope_addr.first().copied()), summary));
+
+        for (dataflow, summary) in operators.chain(channels) {
+            let name = dataflow.map(|d| d.to_string()).unwrap_or_else(|| "shared".to_string());
+            report.entry(name).or_default().merge(summary);
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline::build_pag;
+    use crate::testing::two_dataflow_events;
+
+    #[test]
+    fn summaries_per_dataflow() {
+        let events = two_dataflow_events(2, 5);
+
+        let topology = Topology::from_events(events.iter());
+        let pag = build_pag(events, true);
+        let report = DataflowSummaries::from_edges(pag.iter()).report(&topology);
+
+        assert_eq!(report.keys().collect::<Vec<_>>(), vec!["0", "1", "all", "shared"]);
+        assert_eq!(report["all"], Summary::from_edges(pag.iter()));
+
+        // every edge is summarized exactly once apart from `all`
+        let mut parts = Summary::default();
+        for (_, summary) in report.iter().filter(|(name, _)| *name != "all") {
+            parts.merge(summary);
+        }
+        assert_eq!(parts, report["all"]);
+        assert!(report["0"].activities.contains_key(&EdgeKind::Processing));
+        assert!(report["1"].activities.contains_key(&EdgeKind::Processing));
+        assert!(!report["shared"].activities.contains_key(&EdgeKind::Spinning));
+    }
+}
@@ -0,0 +1,131 @@
+//! Per-worker utilization time series: the fraction of wall time each worker
+//! spends per activity in fixed-size buckets, for plotting alongside
+//! application metrics without going through the dashboard.
+
+#![deny(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeKind};
+use crate::analysis::{Analysis, window_start};
+use crate::topology::Topology;
+
+/// Utilization of a worker within a bucket
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct WorkerUtilization {
+    /// Start of the bucket
+    pub bucket: Duration,
+    /// Worker ID
+    pub wid: usize,
+    /// Fraction of the bucket's wall time spent per activity
+    pub activities: BTreeMap<EdgeKind, f64>,
+}
+
+impl WorkerUtilization {
+    /// Header of the CSV export
+    pub const CSV_HEADER: &'static str = "bucket_ns,wid,activity,fraction";
+
+    /// Exports the utilization as CSV rows, one per activity.
+    pub fn to_csv(&self) -> Vec<String> {
+        self.activities.iter()
+            .map(|(kind, fraction)| format!("{},{},{:?},{}", self.bucket.as_nanos(), self.wid, kind, fraction))
+            .collect()
+    }
+}
+
+/// Collects time per worker and activity in buckets of fixed size.
+/// Local edges spanning several buckets are split up among them. A bucket is
+/// complete once the PAG is complete up to its end.
+#[derive(Clone, Debug)]
+pub struct Utilization {
+    bucket: Duration,
+    /// time by bucket, then (worker, activity)
+    time: BTreeMap<Duration, BTreeMap<(usize, EdgeKind), Duration>>,
+}
+
+impl Utilization {
+    /// Creates an empty series with buckets of size `bucket`.
+    pub fn new(bucket: Duration) -> Self {
+        Utilization {
+            bucket,
+            time: BTreeMap::new(),
+        }
+    }
+
+    /// Incorporates a PAG edge. Remote edges are ignored.
+    pub fn update(&mut self, edge: &PagEdge) {
+        if edge.src.wid != edge.dst.wid {
+            return;
+        }
+
+        let kind = edge.edge_type.kind();
+        let mut start = edge.src.t;
+        while start < edge.dst.t {
+            let bucket = window_start(start, self.bucket);
+            let end = std::cmp::min(edge.dst.t, bucket + self.bucket);
+            *self.time.entry(bucket).or_default().entry((edge.src.wid, kind)).or_default() += end - start;
+            start = end;
+        }
+    }
+
+    /// Utilization per bucket and worker of `time`, ordered by bucket.
+    fn buckets(&self, time: BTreeMap<Duration, BTreeMap<(usize, EdgeKind), Duration>>) -> Vec<WorkerUtilization> {
+        let mut buckets: BTreeMap<(Duration, usize), WorkerUtilization> = BTreeMap::new();
+        for (bucket, (wid, kind), time) in time.into_iter().flat_map(|(b, t)| t.into_iter().map(move |(k, t)| (b, k, t))) {
+            let utilization = buckets.entry((bucket, wid)).or_insert_with(|| WorkerUtilization {
+                bucket,
+                wid,
+                ..Default::default()
+            });
+            utilization.activities.insert(kind, time.as_secs_f64() / self.bucket.as_secs_f64());
+        }
+        buckets.into_values().collect()
+    }
+}
+
+impl Analysis for Utilization {
+    type Output = WorkerUtilization;
+
+    fn update(&mut self, edge: &PagEdge) {
+        Utilization::update(self, edge);
+    }
+
+    fn advance(&mut self, until: Duration, _topology: &Topology) -> Vec<WorkerUtilization> {
+        let open = self.time.split_off(&window_start(until, self.bucket));
+        let complete = std::mem::replace(&mut self.time, open);
+        self.buckets(complete)
+    }
+
+    fn finish(&mut self, _topology: &Topology) -> Vec<WorkerUtilization> {
+        let time = std::mem::take(&mut self.time);
+        self.buckets(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EdgeType;
+    use crate::testing::edge;
+
+    #[test]
+    fn splits_edges_among_buckets() {
+        let topology = Topology::default();
+        let mut utilization = Utilization::new(Duration::from_nanos(100));
+        utilization.update(&edge((0, 0), (150, 0), EdgeType::Busy));
+        utilization.update(&edge((50, 1), (60, 1), EdgeType::Spinning(2)));
+        utilization.update(&edge((60, 1), (70, 0), EdgeType::Progress));
+
+        let buckets = utilization.advance(Duration::from_nanos(120), &topology);
+        assert_eq!(buckets.iter().map(|b| (b.bucket, b.wid)).collect::<Vec<_>>(), vec![(Duration::default(), 0), (Duration::default(), 1)]);
+        assert_eq!(buckets[0].activities, BTreeMap::from([(EdgeKind::Busy, 1.0)]));
+        assert_eq!(buckets[1].activities, BTreeMap::from([(EdgeKind::Spinning, 0.1)]));
+
+        let buckets = utilization.finish(&topology);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!((buckets[0].bucket, buckets[0].activities[&EdgeKind::Busy]), (Duration::from_nanos(100), 0.5));
+    }
+}
@@ -0,0 +1,288 @@
+//! Links `Waiting` edges to the remote activity that unblocked them,
+//! yielding a "who waits on whom" graph per window.
+
+#![deny(missing_docs)]
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, PagNode, EdgeType, EdgeKind, WaitingCause};
+use crate::analysis::{Analysis, window_start};
+use crate::topology::Topology;
+
+/// What the sending worker was doing when it sent an unblocking message
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum SentBy {
+    /// The operator that was processing
+    Operator(usize),
+    /// No operator, e.g. progress is usually sent outside of operators
+    Worker,
+    /// The sender's activity was already forgotten when the message arrived,
+    /// i.e. the message was in flight for longer than a window
+    Unknown,
+}
+
+/// A waiting edge together with the remote edge that ended the wait
+struct Unblocking {
+    /// The `Waiting` edge
+    waiting: PagEdge,
+    /// The remote `Data` or `Progress` edge received right after waiting
+    remote: PagEdge,
+    sent_by: SentBy,
+}
+
+/// Aggregated waiting of one worker on another within a window
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct WaitLink {
+    /// Start of the window
+    pub window: Duration,
+    /// Worker that waited
+    pub waiter: usize,
+    /// Worker that sent the unblocking message
+    pub sender: usize,
+    /// What sent the unblocking message on the sending worker
+    pub sent_by: SentBy,
+    /// What was waited for
+    pub cause: WaitingCause,
+    /// #waiting edges
+    pub count: usize,
+    /// total waiting time
+    pub duration: Duration,
+}
+
+/// (waiter, sender, sent_by, cause)
+type WaitLinkKey = (usize, usize, SentBy, WaitingCause);
+
+/// For each `Waiting` edge caused by remote data or progress, finds the first
+/// remote edge of that type received by the waiting worker at or after the
+/// end of the wait. Waits for external input have no remote cause and are skipped.
+/// These unblockings are aggregated per window (by the end of the wait),
+/// waiting worker, sending worker and sending operator.
+pub fn wait_graph(pag: &[PagEdge], window: Duration) -> Vec<WaitLink> {
+    let mut graph = WaitGraph::new(window);
+    pag.iter().for_each(|edge| graph.update(edge));
+    graph.finish(&Topology::default())
+}
+
+/// Incrementally constructs the wait graph, cf. `wait_graph`. Unblocking messages
+/// are usually received right after the wait, so a window is reported once the PAG
+/// is complete up to one window past its end. Waits that haven't been unblocked
+/// by then are left out. Senders' activity is kept for a window, so messages in
+/// flight for longer are attributed to an `Unknown` sender.
+pub struct WaitGraph {
+    window: Duration,
+    index: Index,
+    /// waits not yet linked to the remote edge that unblocked them
+    pending: Vec<PagEdge>,
+    links: BTreeMap<Duration, BTreeMap<WaitLinkKey, (usize, Duration)>>,
+}
+
+impl WaitGraph {
+    /// Creates an empty wait graph with windows of size `window`.
+    pub fn new(window: Duration) -> Self {
+        WaitGraph {
+            window,
+            index: Index::default(),
+            pending: Vec::new(),
+            links: BTreeMap::new(),
+        }
+    }
+
+    fn link(&mut self, unblocking: Unblocking) {
+        let cause = match unblocking.waiting.edge_type {
+            EdgeType::Waiting(cause) => cause,
+            _ => unreachable!(),
+        };
+        let key = (unblocking.waiting.dst.wid, unblocking.remote.src.wid, unblocking.sent_by, cause);
+
+        let link = self.links
+            .entry(window_start(unblocking.waiting.dst.t, self.window)).or_default()
+            .entry(key).or_default();
+        link.0 += 1;
+        link.1 += unblocking.waiting.duration();
+    }
+
+    fn report(links: BTreeMap<Duration, BTreeMap<WaitLinkKey, (usize, Duration)>>) -> Vec<WaitLink> {
+        links.into_iter()
+            .flat_map(|(window, links)| links.into_iter().map(move |((waiter, sender, sent_by, cause), (count, duration))| {
+                WaitLink { window, waiter, sender, sent_by, cause, count, duration }
+            }))
+            .collect()
+    }
+}
+
+impl Analysis for WaitGraph {
+    type Output = WaitLink;
+
+    fn update(&mut self, edge: &PagEdge) {
+        self.index.insert(edge);
+        if remote_kind(edge).is_some() {
+            self.pending.push(edge.clone());
+        }
+    }
+
+    fn advance(&mut self, until: Duration, _topology: &Topology) -> Vec<WaitLink> {
+        let window = self.window;
+
+        // link waits once their unblocking edge is complete, or give up on them
+        let pending = std::mem::take(&mut self.pending);
+        for waiting in pending {
+            match self.index.unblocking(&waiting) {
+                Some(unblocking) if unblocking.remote.src.t.max(unblocking.remote.dst.t) < until => self.link(unblocking),
+                _ if until >= window_start(waiting.dst.t, window) + 2 * window => {}
+                _ => self.pending.push(waiting),
+            }
+        }
+
+        // edges of waits that are still to come end after `until`
+        let horizon = self.pending.iter().map(|e| e.dst.t).min().unwrap_or(until).min(until);
+        self.index.prune(horizon, horizon.checked_sub(window).unwrap_or_default());
+
+        let open = match until.checked_sub(2 * window) {
+            Some(closed) => window_start(closed, window) + window,
+            None => Duration::default(),
+        };
+        let open = self.links.split_off(&open);
+        WaitGraph::report(std::mem::replace(&mut self.links, open))
+    }
+
+    fn finish(&mut self, _topology: &Topology) -> Vec<WaitLink> {
+        for waiting in std::mem::take(&mut self.pending) {
+            if let Some(unblocking) = self.index.unblocking(&waiting) {
+                self.link(unblocking);
+            }
+        }
+        WaitGraph::report(std::mem::take(&mut self.links))
+    }
+}
+
+/// The kind of remote edge a wait is unblocked by, if any
+fn remote_kind(waiting: &PagEdge) -> Option<EdgeKind> {
+    match waiting.edge_type {
+        EdgeType::Waiting(WaitingCause::Data) => Some(EdgeKind::Data),
+        EdgeType::Waiting(WaitingCause::Progress) => Some(EdgeKind::Progress),
+        _ => None,
+    }
+}
+
+/// Remote edges and operator activity, indexed to look up unblockings
+#[derive(Default)]
+struct Index {
+    /// remote edges by (receiving worker, kind), sorted by receive time
+    remote: HashMap<(usize, EdgeKind), Vec<PagEdge>>,
+    /// local edges of operators by worker, sorted by start time
+    local: HashMap<usize, Vec<PagEdge>>,
+    /// local edges ending before this have been forgotten
+    forgotten: Duration,
+}
+
+impl Index {
+    fn insert(&mut self, edge: &PagEdge) {
+        if edge.src.wid != edge.dst.wid {
+            let edges = self.remote.entry((edge.dst.wid, edge.edge_type.kind())).or_default();
+            let idx = edges.partition_point(|e| e.dst.t <= edge.dst.t);
+            edges.insert(idx, edge.clone());
+        } else if edge.edge_type.oid().is_some() {
+            let edges = self.local.entry(edge.src.wid).or_default();
+            let idx = edges.partition_point(|e| e.src.t <= edge.src.t);
+            edges.insert(idx, edge.clone());
+        }
+    }
+
+    /// Links `waiting` to the first remote edge received after it, if any
+    fn unblocking(&self, waiting: &PagEdge) -> Option<Unblocking> {
+        let candidates = self.remote.get(&(waiting.dst.wid, remote_kind(waiting)?))?;
+        let idx = candidates.partition_point(|e| e.dst.t < waiting.dst.t);
+        let remote = candidates.get(idx)?;
+
+        let sent_by = match self.local.get(&remote.src.wid).and_then(|edges| active_oid(edges, &remote.src)) {
+            Some(oid) => SentBy::Operator(oid),
+            None if remote.src.t < self.forgotten => SentBy::Unknown,
+            None => SentBy::Worker,
+        };
+
+        Some(Unblocking { waiting: waiting.clone(), remote: remote.clone(), sent_by })
+    }
+
+    /// Forgets remote edges received before `remote` and local edges ending before `local`.
+    fn prune(&mut self, remote: Duration, local: Duration) {
+        for edges in self.remote.values_mut() {
+            edges.drain(.. edges.partition_point(|e| e.dst.t < remote));
+        }
+        for edges in self.local.values_mut() {
+            edges.retain(|e| e.dst.t >= local);
+        }
+        self.forgotten = self.forgotten.max(local);
+    }
+}
+
+/// The operator attributed to the local edge of a worker that contains `node`
+fn active_oid(edges: &[PagEdge], node: &PagNode) -> Option<usize> {
+    let idx = edges.partition_point(|e| e.src.t <= node.t);
+    let edge = edges.get(idx.checked_sub(1)?)?;
+    if edge.dst.t >= node.t {
+        edge.edge_type.oid()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{edge, exchange_events, run_incrementally, processing};
+    use crate::offline::build_pag;
+
+    #[test]
+    fn reports_window_a_window_past_its_end() {
+        let mut graph = WaitGraph::new(Duration::from_nanos(100));
+        let topology = Topology::default();
+
+        // worker 0 waits for data sent by operator 3 on worker 1
+        graph.update(&edge((0, 0), (10, 0), EdgeType::Waiting(WaitingCause::Data)));
+        graph.update(&edge((4, 1), (6, 1), processing(3)));
+        graph.update(&edge((5, 1), (11, 0), EdgeType::Data { length: 1, channel: 2 }));
+        // worker 1's wait is never unblocked
+        graph.update(&edge((20, 1), (30, 1), EdgeType::Waiting(WaitingCause::Progress)));
+
+        assert!(graph.advance(Duration::from_nanos(150), &topology).is_empty());
+        assert_eq!(graph.advance(Duration::from_nanos(200), &topology), vec![WaitLink {
+            window: Duration::default(),
+            waiter: 0,
+            sender: 1,
+            sent_by: SentBy::Operator(3),
+            cause: WaitingCause::Data,
+            count: 1,
+            duration: Duration::from_nanos(10),
+        }]);
+        assert!(graph.pending.is_empty());
+        assert!(graph.finish(&topology).is_empty());
+    }
+
+    #[test]
+    fn forgotten_senders_are_unknown() {
+        let mut graph = WaitGraph::new(Duration::from_nanos(100));
+        let topology = Topology::default();
+
+        // operator 3's activity is forgotten before the message it sent arrives
+        graph.update(&edge((4, 1), (6, 1), processing(3)));
+        assert!(graph.advance(Duration::from_nanos(300), &topology).is_empty());
+
+        graph.update(&edge((300, 0), (390, 0), EdgeType::Waiting(WaitingCause::Data)));
+        graph.update(&edge((5, 1), (391, 0), EdgeType::Data { length: 1, channel: 2 }));
+        let links = graph.advance(Duration::from_nanos(600), &topology);
+        assert_eq!(links.iter().map(|l| (l.window, l.sent_by)).collect::<Vec<_>>(), vec![(Duration::from_nanos(300), SentBy::Unknown)]);
+    }
+
+    #[test]
+    fn links_incrementally_as_in_batch() {
+        let pag = build_pag(exchange_events(2, 10), true);
+        let window = Duration::from_secs(1);
+
+        let incremental = run_incrementally(WaitGraph::new(window), &pag, Duration::from_micros(100), &Topology::default());
+        assert!(!incremental.is_empty());
+        assert_eq!(incremental, wait_graph(&pag, window));
+    }
+}
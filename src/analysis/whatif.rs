@@ -0,0 +1,271 @@
+//! What-if analysis: projects the end-to-end effect of an optimization before
+//! implementing it, by re-evaluating the critical path with scaled edge weights.
+//! Since the longest path is re-evaluated, a different path may become critical.
+
+#![deny(missing_docs)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{PagEdge, EdgeType};
+use crate::analysis::Analysis;
+use crate::analysis::critical::{self, longest_path, WindowedEdges};
+use crate::topology::Topology;
+
+/// A hypothetical optimization
+#[derive(Clone, PartialEq, Debug)]
+pub enum Scenario {
+    /// Make an operator faster, i.e. shorten its work (cf. `EdgeKind::is_operator_work`).
+    /// Its scheduling overhead (`Spinning`) stays the same.
+    Operator {
+        /// Operator ID or name; a name selects all operators of that name
+        operator: String,
+        /// Speedup in percent of the original duration, e.g. `50` halves it
+        faster: f64,
+    },
+    /// Remove an exchange, i.e. make remote data messages on a channel free
+    Exchange {
+        /// ID of the channel
+        channel: usize,
+    },
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    /// Parses `operator=<oid or name>:<percent>` or `exchange=<channel>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, target) = s.split_once('=').ok_or_else(|| format!("missing '=' in scenario {}", s))?;
+        match kind.trim() {
+            "operator" => {
+                let (operator, faster) = target.rsplit_once(':').ok_or_else(|| format!("missing ':<percent>' in scenario {}", s))?;
+                let faster: f64 = faster.trim().parse().map_err(|_| format!("couldn't parse percentage in scenario {}", s))?;
+                Ok(Scenario::Operator { operator: operator.trim().to_string(), faster: faster.clamp(0.0, 100.0) })
+            }
+            "exchange" => {
+                let channel = target.trim().parse().map_err(|_| format!("couldn't parse channel in scenario {}", s))?;
+                Ok(Scenario::Exchange { channel })
+            }
+            other => Err(format!("unknown scenario kind: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scenario::Operator { operator, faster } => write!(f, "operator={}:{}", operator, faster),
+            Scenario::Exchange { channel } => write!(f, "exchange={}", channel),
+        }
+    }
+}
+
+/// Projected effect of a scenario on the critical path
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Projection {
+    /// The scenario, as parsed
+    pub scenario: String,
+    /// Start of the window, `None` for the whole run
+    pub window: Option<Duration>,
+    /// Length of the critical path as is
+    pub baseline: Duration,
+    /// Length of the critical path with the scenario applied
+    pub projected: Duration,
+    /// `baseline - projected`
+    pub saved: Duration,
+    /// `baseline / projected`, `None` if nothing is left of the critical path
+    pub speedup: Option<f64>,
+}
+
+impl Projection {
+    fn new(scenario: &Scenario, window: Option<Duration>, baseline: Duration, projected: Duration) -> Self {
+        Projection {
+            scenario: scenario.to_string(),
+            window,
+            baseline,
+            projected,
+            saved: baseline.checked_sub(projected).unwrap_or_default(),
+            speedup: if projected > Duration::default() {
+                Some(baseline.as_secs_f64() / projected.as_secs_f64())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Projects the effect of `scenario` per window and for the whole run.
+/// Operator names are resolved using `topology`.
+pub fn what_if(pag: &[PagEdge], scenario: &Scenario, window: Duration, topology: &Topology) -> Vec<Projection> {
+    let mut what_if = WhatIf::new(vec![scenario.clone()], window);
+    pag.iter().for_each(|edge| what_if.update(edge));
+    what_if.finish(topology)
+}
+
+/// Incrementally projects the effect of scenarios, cf. `what_if`. A window is
+/// projected once the PAG is complete up to its end (cf. `critical`). The critical
+/// path of the whole run isn't kept track of, so the whole run is projected as
+/// the sum of all windows' critical paths. Like `CriticalPaths`, this relies on the
+/// untrimmed PAG, otherwise remote edges hardly ever lie on the critical path.
+pub struct WhatIf {
+    scenarios: Vec<Scenario>,
+    windows: WindowedEdges,
+    /// (baseline, projected) of the whole run so far, by scenario
+    totals: Vec<(Duration, Duration)>,
+}
+
+impl WhatIf {
+    /// Creates an analysis projecting `scenarios` per window of size `window`.
+    pub fn new(scenarios: Vec<Scenario>, window: Duration) -> Self {
+        WhatIf {
+            totals: vec![Default::default(); scenarios.len()],
+            scenarios,
+            windows: WindowedEdges::new(window),
+        }
+    }
+
+    fn project(&mut self, windows: BTreeMap<Duration, Vec<PagEdge>>, topology: &Topology) -> Vec<Projection> {
+        let mut projections = Vec::new();
+        for (scenario, total) in self.scenarios.iter().zip(self.totals.iter_mut()) {
+            let weight = scaled_weight(scenario, topology);
+            for (window, edges) in windows.iter() {
+                let baseline = longest_path(edges.iter(), critical::weight).length;
+                let projected = longest_path(edges.iter(), &weight).length;
+                total.0 += baseline;
+                total.1 += projected;
+                projections.push(Projection::new(scenario, Some(*window), baseline, projected));
+            }
+        }
+        projections
+    }
+}
+
+impl Analysis for WhatIf {
+    type Output = Projection;
+
+    fn update(&mut self, edge: &PagEdge) {
+        self.windows.insert(edge);
+    }
+
+    fn advance(&mut self, until: Duration, topology: &Topology) -> Vec<Projection> {
+        let windows = self.windows.complete(until);
+        self.project(windows, topology)
+    }
+
+    fn finish(&mut self, topology: &Topology) -> Vec<Projection> {
+        let windows = self.windows.complete(Duration::MAX);
+        let mut projections = self.project(windows, topology);
+        projections.extend(self.scenarios.iter().zip(self.totals.iter())
+            .map(|(scenario, (baseline, projected))| Projection::new(scenario, None, *baseline, *projected)));
+        projections
+    }
+}
+
+/// Critical path weights with the scenario applied
+fn scaled_weight<'a>(scenario: &'a Scenario, topology: &Topology) -> impl Fn(&PagEdge) -> Duration + 'a {
+    let oids: BTreeSet<usize> = match scenario {
+        Scenario::Operator { operator, .. } => match operator.parse() {
+            Ok(oid) => Some(oid).into_iter().collect(),
+            Err(_) => topology.operators.values().filter(|op| &op.name == operator).map(|op| op.id).collect(),
+        },
+        Scenario::Exchange { .. } => BTreeSet::new(),
+    };
+
+    move |edge: &PagEdge| {
+        let weight = critical::weight(edge);
+        match (scenario, edge.edge_type) {
+            (Scenario::Operator { faster, .. }, edge_type) if edge_type.kind().is_operator_work() && edge_type.oid().is_some_and(|oid| oids.contains(&oid)) => {
+                weight.mul_f64(1.0 - faster / 100.0)
+            }
+            (Scenario::Exchange { channel }, EdgeType::Data { channel: c, .. }) if *channel == c && edge.src.wid != edge.dst.wid => {
+                Duration::default()
+            }
+            _ => weight,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EdgeKind, WaitingCause};
+    use crate::testing::{edge, processing};
+
+    #[test]
+    fn parses_scenarios() {
+        assert_eq!("operator=Probe:50".parse(), Ok(Scenario::Operator { operator: "Probe".to_string(), faster: 50.0 }));
+        assert_eq!("exchange=3".parse(), Ok(Scenario::Exchange { channel: 3 }));
+        assert!("exchange=x".parse::<Scenario>().is_err());
+    }
+
+    #[test]
+    fn projects_windows_and_their_sum() {
+        let pag = [
+            edge((0, 0), (10, 0), processing(1)),
+            edge((10, 0), (30, 0), processing(2)),
+            edge((100, 0), (140, 0), processing(1)),
+        ];
+        let scenario = "operator=1:50".parse().unwrap();
+
+        let projections = what_if(&pag, &scenario, Duration::from_nanos(100), &Topology::default());
+        let lengths: Vec<_> = projections.iter().map(|p| (p.window, p.baseline.as_nanos(), p.projected.as_nanos())).collect();
+        assert_eq!(lengths, vec![
+            (Some(Duration::default()), 30, 25),
+            (Some(Duration::from_nanos(100)), 40, 20),
+            (None, 70, 45),
+        ]);
+        assert_eq!(projections[1].speedup, Some(2.0));
+    }
+
+    #[test]
+    fn operators_spin_as_before() {
+        let pag = [
+            edge((0, 0), (10, 0), processing(1)),
+            edge((10, 0), (30, 0), EdgeType::Spinning(1)),
+        ];
+        let scenario = "operator=1:50".parse().unwrap();
+
+        let projections = what_if(&pag, &scenario, Duration::from_nanos(100), &Topology::default());
+        assert_eq!((projections[0].baseline, projections[0].projected), (Duration::from_nanos(30), Duration::from_nanos(25)));
+    }
+
+    #[test]
+    fn removed_exchange_leaves_the_path() {
+        let data = edge((20, 1), (35, 0), EdgeType::Data { length: 1, channel: 3 });
+        let pag = [
+            // worker 0 waits for data from worker 1
+            edge((0, 0), (25, 0), processing(1)),
+            edge((25, 0), (35, 0), EdgeType::Waiting(WaitingCause::Data)),
+            edge((35, 0), (45, 0), processing(1)),
+            edge((0, 1), (20, 1), processing(2)),
+            data.clone(),
+        ];
+        let scenario = "exchange=3".parse().unwrap();
+
+        let baseline = longest_path(pag.iter(), critical::weight);
+        assert_eq!(baseline.length, Duration::from_nanos(45));
+        assert!(baseline.edges.contains(&data));
+
+        // without the exchange, worker 0's own processing is critical
+        let projected = longest_path(pag.iter(), scaled_weight(&scenario, &Topology::default()));
+        assert_eq!(projected.length, Duration::from_nanos(35));
+        assert!(projected.edges.iter().all(|e| e.edge_type.kind() != EdgeKind::Data));
+
+        let projections = what_if(&pag, &scenario, Duration::from_nanos(100), &Topology::default());
+        assert_eq!((projections[0].baseline, projections[0].projected), (baseline.length, projected.length));
+    }
+
+    #[test]
+    fn no_speedup_if_nothing_is_left() {
+        let pag = [edge((0, 0), (10, 0), processing(1))];
+        let scenario = "operator=1:100".parse().unwrap();
+
+        let projections = what_if(&pag, &scenario, Duration::from_nanos(100), &Topology::default());
+        assert_eq!(projections[0].saved, Duration::from_nanos(10));
+        assert_eq!(projections[0].speedup, None);
+    }
+}
@@ -0,0 +1,153 @@
+//! In-process profiling: the source computation routes its own log events
+//! to a background thread that constructs the PAG, instead of shipping them
+//! to a separate st2 process.
+
+#![deny(missing_docs)]
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use timely::communication::Allocate;
+use timely::logging::TimelyEvent;
+use timely::worker::Worker;
+
+use crate::Event;
+use crate::offline::IncrementalPag;
+use crate::analysis::summary::DataflowSummaries;
+use crate::topology::Topology;
+
+/// Handle to a background thread constructing the PAG of the running computation.
+/// Create it before `timely::execute`, `install` it on every worker, and
+/// `finish` it after the computation has completed:
+///
+/// ```ignore
+/// let profiler = Profiler::new(".")?;
+/// let worker_profiler = profiler.clone();
+/// timely::execute_from_args(std::env::args(), move |worker| {
+///     worker_profiler.install(worker);
+///     // ... construct and run dataflows
+/// }).unwrap();
+/// profiler.finish()?;
+/// ```
+#[derive(Clone)]
+pub struct Profiler {
+    sender: Sender<Vec<Event>>,
+    handle: Arc<Mutex<Option<JoinHandle<io::Result<()>>>>>,
+}
+
+impl Profiler {
+    /// Spawns the background thread, which constructs the (trimmed) PAG as events arrive
+    /// and writes it to `<dir>/pag.json` as a JSON array of edges. Once all events have
+    /// been received, a summary of time spent per activity type is written to
+    /// `<dir>/summary.json` (as written by st2).
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let pag_file = File::create(dir.join("pag.json"))?;
+        let summary_file = File::create(dir.join("summary.json"))?;
+        let (sender, receiver) = mpsc::channel::<Vec<Event>>();
+
+        let handle = thread::spawn(move || {
+            let mut pag = IncrementalPag::new(true);
+            let mut topology = Topology::default();
+            let mut summaries = DataflowSummaries::default();
+
+            let mut writer = BufWriter::new(pag_file);
+            let mut separator = "";
+            write!(writer, "[")?;
+            for event in receiver.into_iter().flatten() {
+                topology.update(&event);
+                for edge in pag.push(event) {
+                    summaries.update(&edge);
+                    write!(writer, "{}\n{}", separator, serde_json::to_string(&edge)?)?;
+                    separator = ",";
+                }
+            }
+            writeln!(writer, "\n]")?;
+            writer.flush()?;
+
+            serde_json::to_writer(summary_file, &summaries.report(&topology))?;
+            Ok(())
+        });
+
+        Ok(Profiler {
+            sender,
+            handle: Arc::new(Mutex::new(Some(handle))),
+        })
+    }
+
+    /// Registers a `timely` logger on `worker` that forwards its events to the profiler.
+    pub fn install<A: Allocate>(&self, worker: &mut Worker<A>) {
+        let sender = self.sender.clone();
+
+        worker
+            .log_register()
+            .insert::<TimelyEvent, _>("timely", move |_time, data| {
+                // the profiler might already have shut down, which is fine
+                let _ = sender.send(std::mem::take(data));
+            });
+    }
+
+    /// Waits for the PAG and summary to be written, returning any error writing them.
+    /// Loggers are only closed once their worker has shut down, so call this after
+    /// the computation has completed (i.e., its `WorkerGuards` have been dropped)
+    /// and all other clones of this profiler are gone.
+    pub fn finish(self) -> io::Result<()> {
+        let handle = self.handle.lock().expect("profiler lock poisoned").take();
+        drop(self.sender);
+
+        match handle {
+            Some(handle) => handle.join().expect("profiler thread panicked"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PagEdge;
+    use crate::analysis::summary::Summary;
+
+    use std::collections::BTreeMap;
+    use timely::dataflow::InputHandle;
+    use timely::dataflow::operators::{Exchange, Input, Probe};
+
+    #[test]
+    fn writes_pag_and_summary() {
+        let dir = std::env::temp_dir().join(format!("st2-embedded-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let profiler = Profiler::new(&dir).unwrap();
+        let worker_profiler = profiler.clone();
+        timely::execute(timely::Configuration::Process(2), move |worker| {
+            worker_profiler.install(worker);
+
+            let mut input = InputHandle::new();
+            let probe = worker.dataflow(|scope| scope.input_from(&mut input).exchange(|x: &u64| *x).probe());
+            for round in 0 .. 5u64 {
+                input.send(round);
+                input.advance_to(round + 1);
+                while probe.less_than(input.time()) {
+                    worker.step();
+                }
+            }
+        }).unwrap();
+        profiler.finish().unwrap();
+
+        let pag: Vec<PagEdge> = serde_json::from_str(&std::fs::read_to_string(dir.join("pag.json")).unwrap()).unwrap();
+        assert!(pag.iter().any(|e| e.src.wid != e.dst.wid));
+        let summary: BTreeMap<String, Summary> = serde_json::from_str(&std::fs::read_to_string(dir.join("summary.json")).unwrap()).unwrap();
+        assert_eq!(summary["all"].activities.values().map(|a| a.count).sum::<usize>(), pag.len());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fails_on_missing_directory() {
+        assert!(Profiler::new(std::env::temp_dir().join("st2-does-not-exist").join("nested")).is_err());
+    }
+}
@@ -9,13 +9,23 @@ use timely::logging::TimelyEvent;
 
 use serde::{Serialize, Deserialize};
 
-use crate::EdgeType::{Processing, Spinning, Progress, Data, Waiting, Busy};
+pub mod pag;
+pub mod offline;
+pub mod embedded;
+pub mod logging;
+pub mod topology;
+pub mod analysis;
+
+#[cfg(test)]
+mod testing;
+
+use crate::EdgeType::{Processing, Spinning, Progress, Data, Waiting, Busy, Parked, GuardedMessage, GuardedProgress};
 
 /// event type as provided by Timely backend
 pub type Event = (Duration, usize, TimelyEvent);
 
 /// The various types of activity that can happen in a dataflow.
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum EdgeType {
     /// Operator actually doing work
     Processing {
@@ -31,29 +41,130 @@ pub enum EdgeType {
     /// remote control messages, e.g. about progress
     Progress,
     /// remote data messages, e.g. moving tuples around
-    Data(usize),
-    /// Waiting for unblocking.
-    /// In particular, operator might wait for external input.
-    Waiting,
+    Data {
+        /// #records sent
+        length: usize,
+        /// ID of the channel the records were sent on
+        channel: usize,
+    },
+    /// Waiting for unblocking, e.g. on remote data or external input.
+    Waiting(WaitingCause),
     /// Waiting where next activity is actively prepared,
     /// e.g. in-between a ScheduleEnd and consecutive ScheduleStart.
     /// In particular, operator doesn't depend on external input.
-    Busy
+    Busy,
+    /// Worker thread parked by the OS, i.e. in-between a Park and Unpark event.
+    /// In contrast to `Waiting`, the worker isn't actively polling for work.
+    Parked,
+    /// Operator handling received messages, i.e. in-between a GuardedMessage
+    /// start and stop event (around the logic passed to `InputHandle::for_each`)
+    GuardedMessage {
+        /// operator ID
+        oid: Option<usize>,
+        /// #messages sent
+        send: Option<usize>,
+        /// #messages received
+        recv: Option<usize>,
+    },
+    /// Operator handling progress notifications, i.e. in-between a GuardedProgress
+    /// start and stop event (around the logic passed to `Notificator::for_each`)
+    GuardedProgress {
+        /// operator ID
+        oid: Option<usize>,
+        /// #messages sent
+        send: Option<usize>,
+        /// #messages received
+        recv: Option<usize>,
+    },
 }
 
-impl PartialEq for EdgeType {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Processing { oid: oid1, .. },
-             Processing { oid: oid2, .. }) => oid1 == oid2,
-            (Spinning(x), Spinning(y)) => x == y,
-            (Progress, Progress) => true,
-            (Data(_), Data(_)) => true,
-            (Waiting, Waiting) => true,
-            (Busy, Busy) => true,
-            _ => false
+impl EdgeType {
+    /// The payload-free kind of this activity
+    pub fn kind(&self) -> EdgeKind {
+        match self {
+            Processing { .. } => EdgeKind::Processing,
+            Spinning(_) => EdgeKind::Spinning,
+            Progress => EdgeKind::Progress,
+            Data { .. } => EdgeKind::Data,
+            Waiting(WaitingCause::Data) => EdgeKind::WaitingForData,
+            Waiting(WaitingCause::Progress) => EdgeKind::WaitingForProgress,
+            Waiting(WaitingCause::Input) => EdgeKind::WaitingForInput,
+            Busy => EdgeKind::Busy,
+            Parked => EdgeKind::Parked,
+            GuardedMessage { .. } => EdgeKind::GuardedMessage,
+            GuardedProgress { .. } => EdgeKind::GuardedProgress,
         }
     }
+
+    /// The operator this activity is attributed to, if any
+    pub fn oid(&self) -> Option<usize> {
+        match self {
+            Processing { oid, .. } | GuardedMessage { oid, .. } | GuardedProgress { oid, .. } => *oid,
+            Spinning(oid) => Some(*oid),
+            _ => None,
+        }
+    }
+
+    /// The channel this activity is attributed to, if any
+    pub fn channel(&self) -> Option<usize> {
+        match self {
+            Data { channel, .. } => Some(*channel),
+            _ => None,
+        }
+    }
+}
+
+/// What a `Waiting` activity is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum WaitingCause {
+    /// remote data messages, received right after waiting
+    Data,
+    /// remote progress messages, i.e. coordination with other workers
+    Progress,
+    /// external input, provided to the worker from outside any operator
+    Input,
+}
+
+/// The kind of an activity, without its payload.
+/// Use this to group edges, e.g. when aggregating activities by type.
+/// `Waiting` activities are split up by their cause.
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EdgeKind {
+    /// cf. `EdgeType::Processing`
+    Processing,
+    /// cf. `EdgeType::Spinning`
+    Spinning,
+    /// cf. `EdgeType::Progress`
+    Progress,
+    /// cf. `EdgeType::Data`
+    Data,
+    /// cf. `EdgeType::Waiting` and `WaitingCause::Data`
+    WaitingForData,
+    /// cf. `EdgeType::Waiting` and `WaitingCause::Progress`
+    WaitingForProgress,
+    /// cf. `EdgeType::Waiting` and `WaitingCause::Input`
+    WaitingForInput,
+    /// cf. `EdgeType::Busy`
+    Busy,
+    /// cf. `EdgeType::Parked`
+    Parked,
+    /// cf. `EdgeType::GuardedMessage`
+    GuardedMessage,
+    /// cf. `EdgeType::GuardedProgress`
+    GuardedProgress,
+}
+
+impl EdgeKind {
+    /// Whether this is a waiting activity, regardless of its cause
+    pub fn is_waiting(&self) -> bool {
+        matches!(self, EdgeKind::WaitingForData | EdgeKind::WaitingForProgress | EdgeKind::WaitingForInput)
+    }
+
+    /// Whether this is an operator doing work, including its communication guards.
+    /// Analyses use this to tell work apart from scheduling overhead and waiting.
+    pub fn is_operator_work(&self) -> bool {
+        matches!(self, EdgeKind::Processing | EdgeKind::GuardedMessage | EdgeKind::GuardedProgress)
+    }
 }
 
 /// A node in the PAG
@@ -75,3 +186,12 @@ pub struct PagEdge {
     /// The activity type
     pub edge_type: EdgeType,
 }
+
+impl PagEdge {
+    /// Time between the edge's source and destination.
+    /// Zero for remote edges whose destination precedes their source,
+    /// which can happen due to clock skew between workers.
+    pub fn duration(&self) -> Duration {
+        self.dst.t.checked_sub(self.src.t).unwrap_or_default()
+    }
+}
@@ -0,0 +1,189 @@
+//! Source-side logging helpers: instrument a Timely / DD computation s.t.
+//! its log events can be picked up by st2, either online (TCP) or offline (files).
+//! Timely events st2 doesn't need are filtered out before they are written,
+//! which reduces logging overhead and network volume of the source computation.
+
+#![deny(missing_docs)]
+
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+use timely::communication::Allocate;
+use timely::dataflow::operators::capture::EventWriter;
+use timely::dataflow::operators::capture::event::{Event as CaptureEvent, EventPusher};
+use timely::logging::{TimelyEvent, WorkerIdentifier};
+use timely::logging::TimelyEvent::{Operates, Channels, Shutdown};
+use timely::worker::Worker;
+
+use differential_dataflow::logging::DifferentialEvent;
+
+use crate::Event;
+use crate::pag::Peeler;
+
+/// A logged differential event
+type DifferentialLogEvent = (Duration, WorkerIdentifier, DifferentialEvent);
+
+/// Logs timely events to `<dir>/<worker index>.dump`, as read by `st2 <#workers> f`,
+/// and differential events to `<dir>/<worker index>.differential.dump`.
+pub fn install_file_logger<A: Allocate, P: AsRef<Path>>(worker: &mut Worker<A>, dir: P) -> io::Result<()> {
+    let dir = dir.as_ref();
+    let timely_file = File::create(dir.join(format!("{}.dump", worker.index())))?;
+    let differential_file = File::create(dir.join(format!("{}.differential.dump", worker.index())))?;
+
+    register_timely(worker, timely_file);
+    register_differential(worker, differential_file);
+    Ok(())
+}
+
+/// Logs timely events to the st2 instance listening on `addr`.
+/// st2 expects a single connection per worker, so differential events
+/// are logged separately, cf. `install_differential_tcp_logger`.
+pub fn install_tcp_logger<A: Allocate, T: ToSocketAddrs>(worker: &mut Worker<A>, addr: T) -> io::Result<()> {
+    let timely_stream = TcpStream::connect(addr)?;
+    register_timely(worker, timely_stream);
+    Ok(())
+}
+
+/// Logs differential events to `addr`, e.g. to a tool listening on `DIFFERENTIAL_LOG_ADDR`
+/// (st2 lite doesn't consume them itself).
+pub fn install_differential_tcp_logger<A: Allocate, T: ToSocketAddrs>(worker: &mut Worker<A>, addr: T) -> io::Result<()> {
+    let differential_stream = TcpStream::connect(addr)?;
+    register_differential(worker, differential_stream);
+    Ok(())
+}
+
+fn register_timely<A: Allocate, W: io::Write + 'static>(worker: &mut Worker<A>, timely_writer: W) {
+    let writer = EventWriter::<Duration, Event, _>::new(timely_writer);
+    let mut logger = SparseLogger::new(writer, timely_filter());
+    worker
+        .log_register()
+        .insert::<TimelyEvent, _>("timely", move |time, data| {
+            logger.publish_batch(time, data)
+        });
+}
+
+/// Whether a timely event is needed by st2. `Operates` are kept s.t. st2 can peel
+/// the (already peeled) stream again, `Channels` and `Shutdown` s.t. it can
+/// reconstruct the topology.
+fn timely_filter() -> impl FnMut(&Event) -> bool {
+    let mut peeler = Peeler::default();
+    move |x: &Event| peeler.keep(x) || matches!(x.2, Operates(_) | Channels(_) | Shutdown(_))
+}
+
+fn register_differential<A: Allocate, W: io::Write + 'static>(worker: &mut Worker<A>, differential_writer: W) {
+    let writer = EventWriter::<Duration, DifferentialLogEvent, _>::new(differential_writer);
+    let mut logger = SparseLogger::new(writer, |_: &DifferentialLogEvent| true);
+    worker
+        .log_register()
+        .insert::<DifferentialEvent, _>("differential/arrange", move |time, data| {
+            logger.publish_batch(time, data)
+        });
+}
+
+/// Minimum advancement of the logging frontier before it's published on its own.
+const PROGRESS_GRANULARITY: Duration = Duration::from_millis(10);
+
+/// Like `BatchLogger`, but only publishes events passing `filter`.
+/// Empty batches are skipped entirely, and the frontier is only published along
+/// with data or once it advanced by `PROGRESS_GRANULARITY`.
+struct SparseLogger<D, P: EventPusher<Duration, D>, F: FnMut(&D) -> bool> {
+    time: Duration,
+    event_pusher: P,
+    filter: F,
+    _marker: PhantomData<D>,
+}
+
+impl<D, P: EventPusher<Duration, D>, F: FnMut(&D) -> bool> SparseLogger<D, P, F> {
+    fn new(event_pusher: P, filter: F) -> Self {
+        SparseLogger {
+            time: Default::default(),
+            event_pusher,
+            filter,
+            _marker: PhantomData,
+        }
+    }
+
+    fn publish_batch(&mut self, time: &Duration, data: &mut Vec<D>) {
+        let filter = &mut self.filter;
+        data.retain(|x| filter(x));
+
+        let has_data = !data.is_empty();
+        if has_data {
+            self.event_pusher.push(CaptureEvent::Messages(self.time, std::mem::take(data)));
+        }
+        if self.time < *time && (has_data || *time >= self.time + PROGRESS_GRANULARITY) {
+            self.event_pusher.push(CaptureEvent::Progress(vec![(*time, 1), (self.time, -1)]));
+            self.time = *time;
+        }
+    }
+}
+
+impl<D, P: EventPusher<Duration, D>, F: FnMut(&D) -> bool> Drop for SparseLogger<D, P, F> {
+    fn drop(&mut self) {
+        self.event_pusher.push(CaptureEvent::Progress(vec![(self.time, -1)]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline::build_pag;
+    use crate::testing::{exchange_events, sorted};
+
+    use std::sync::mpsc;
+
+    /// Data published to `receiver`, in order
+    fn published<D>(receiver: mpsc::Receiver<CaptureEvent<Duration, D>>) -> Vec<D> {
+        receiver.into_iter().flat_map(|x| match x {
+            CaptureEvent::Messages(_, data) => data,
+            CaptureEvent::Progress(_) => Vec::new(),
+        }).collect()
+    }
+
+    #[test]
+    fn filtered_events_construct_the_same_pag() {
+        let events = exchange_events(2, 10);
+        let (sender, receiver) = mpsc::channel();
+
+        for wid in 0 .. 2 {
+            let mut logger = SparseLogger::new(sender.clone(), timely_filter());
+            for event in events.iter().filter(|x| x.1 == wid) {
+                logger.publish_batch(&event.0, &mut vec![event.clone()]);
+            }
+        }
+        drop(sender);
+
+        let filtered = published(receiver);
+        assert!(filtered.len() < events.len());
+        for trim in &[false, true] {
+            assert_eq!(sorted(build_pag(filtered.clone(), *trim)), sorted(build_pag(events.clone(), *trim)));
+        }
+    }
+
+    #[test]
+    fn publishes_frontier_sparsely() {
+        let ms = Duration::from_millis;
+        let (sender, receiver) = mpsc::channel();
+
+        let mut logger = SparseLogger::new(sender, |x: &u64| *x > 1);
+        // filtered out, and the frontier didn't advance far enough to be published on its own
+        logger.publish_batch(&ms(1), &mut vec![1]);
+        // published along with data
+        logger.publish_batch(&ms(5), &mut vec![0, 2, 3]);
+        logger.publish_batch(&ms(9), &mut Vec::new());
+        // advanced by `PROGRESS_GRANULARITY`
+        logger.publish_batch(&ms(15), &mut Vec::new());
+        drop(logger);
+
+        assert_eq!(receiver.into_iter().collect::<Vec<_>>(), vec![
+            CaptureEvent::Messages(ms(0), vec![2, 3]),
+            CaptureEvent::Progress(vec![(ms(5), 1), (ms(0), -1)]),
+            CaptureEvent::Progress(vec![(ms(15), 1), (ms(5), -1)]),
+            CaptureEvent::Progress(vec![(ms(15), -1)]),
+        ]);
+    }
+}
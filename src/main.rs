@@ -1,31 +1,98 @@
 use std::time::Duration;
 use std::sync::{Mutex, Arc};
 use std::path::PathBuf;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 
 use tdiag_connect::receive as connect;
 use tdiag_connect::receive::ReplaySource;
 
 use timely::dataflow::operators::capture::replay::Replay;
 use timely::dataflow::operators::inspect::Inspect;
+use timely::dataflow::operators::exchange::Exchange;
+use timely::dataflow::operators::filter::Filter;
 use timely::dataflow::operators::concat::Concat;
-
-mod pag;
-use crate::pag::Pag;
-use crate::pag::TrimPag;
+use timely::dataflow::{Stream, Scope};
+use timely::logging::TimelyEvent;
+use timely::Data;
+
+use st2::pag::{PagBuilder, Pag, TrimPag, SelectDataflows};
+use st2::topology::Topology;
+use st2::analysis::Analyze;
+use st2::analysis::summary::DataflowSummaries;
+use st2::analysis::scopes::ScopeProfile;
+use st2::analysis::waits::WaitGraph;
+use st2::analysis::processing::ProcessingHistograms;
+use st2::analysis::latency::RemoteLatency;
+use st2::analysis::outliers::Outliers;
+use st2::analysis::scheduling::SchedulingProfile;
+use st2::analysis::progress::ProgressOverhead;
+use st2::analysis::parallelism::{ParallelismProfile, ParallelismStep};
+use st2::analysis::utilization::{Utilization, WorkerUtilization};
+use st2::analysis::critical::CriticalPaths;
+use st2::analysis::whatif::{WhatIf, Scenario};
+use st2::Event;
+
+use serde::Serialize;
 
 fn main() {
     let source_peers: usize = std::env::args().nth(1).unwrap().parse().unwrap();
     let from_file: bool = if let Some(f) = std::env::args().nth(2) {
-        f == "f".to_string()
+        f == "f"
     } else {
         false
     };
     let replay_source = make_replay_source(source_peers, from_file);
 
+    // optionally restrict analysis to some dataflows, e.g. `ST2_DATAFLOWS=0,2`
+    let dataflows: Option<BTreeSet<usize>> = std::env::var("ST2_DATAFLOWS").ok().map(|x| {
+        x.split(',').map(|d| d.trim().parse().expect("couldn't parse dataflow")).collect()
+    });
+
+    // optional analyses on the complete PAG, e.g. `ST2_ANALYSES=scopes,waits`
+    let mut analyses: Vec<String> = std::env::var("ST2_ANALYSES")
+        .map(|x| x.split(',').map(|a| a.trim().to_string()).collect())
+        .unwrap_or_default();
+
+    // `ST2_SCOPES=1` is short for `ST2_ANALYSES=scopes`
+    if std::env::var("ST2_SCOPES").is_ok() && !analyses.iter().any(|a| a == "scopes") {
+        analyses.push("scopes".to_string());
+    }
+
+    // window size for windowed analyses in ms, e.g. `ST2_WINDOW=100`
+    let window = Duration::from_millis(std::env::var("ST2_WINDOW")
+        .map(|x| x.parse().expect("couldn't parse window"))
+        .unwrap_or(1000));
+
+    // #longest edges per window and kind reported by `outliers`, e.g. `ST2_TOP_K=10`
+    let top_k: usize = std::env::var("ST2_TOP_K")
+        .map(|x| x.parse().expect("couldn't parse top k"))
+        .unwrap_or(5);
+
+    // scenarios projected by `whatif`, e.g. `ST2_WHATIF=operator=Probe:50,exchange=3`
+    let scenarios: Vec<Scenario> = std::env::var("ST2_WHATIF")
+        .map(|x| x.split(',').map(|s| s.parse().unwrap_or_else(|e| panic!("{}", e))).collect())
+        .unwrap_or_default();
+
     timely::execute_from_args(std::env::args(), move |worker| {
         // read replayers from file (offline) or TCP stream (online)
         let readers = connect::make_readers(replay_source.clone(), worker.index(), worker.peers()).expect("couldn't create readers");
         let _peers = worker.peers();
+        let index = worker.index();
+
+        let topology = Rc::new(RefCell::new(Topology::default()));
+        let topology_sink = topology.clone();
+
+        let summaries = Rc::new(RefCell::new(DataflowSummaries::default()));
+        let summaries_sink = summaries.clone();
+        let dataflows = dataflows.clone();
+
+        let analyses = analyses.clone();
+        let analyze_progress = analyses.iter().any(|a| a == "progress");
+        let scenarios = scenarios.clone();
 
         worker.dataflow::<Duration, _, _>(move |scope| {
             // @TODO: differential
@@ -34,22 +101,126 @@ fn main() {
 
             let stream = readers.replay_into(scope);
 
-            let peeled = stream.peel();
-
-            let local_edges = peeled.local_edges().trim_local();
-            let remote_edges = peeled.remote_edges();
-
-            let pag = local_edges.concat(&remote_edges);
-
+            // lifetimes span all source workers, so their topology events are collected at worker 0
+            let topology_events = stream
+                .filter(|x: &Event| matches!(x.2, TimelyEvent::Operates(_) | TimelyEvent::Channels(_) | TimelyEvent::Shutdown(_)))
+                .exchange(|_| 0);
+            topology_events.inspect(move |x| topology_sink.borrow_mut().update(x));
+
+            // The PAG is constructed for all dataflows, s.t. activity of other dataflows
+            // isn't mistaken for waiting or busy time. Edges of deselected dataflows are
+            // dropped afterwards (after trimming, s.t. edges aren't merged across them);
+            // edges not attributed to any dataflow are kept.
+            // Some analyses rely on the untrimmed PAG, the rest are run on the trimmed one.
+            let untrimmed = PagBuilder::new(&stream).trim(false).build();
+            let select = |pag: Stream<_, _>| match dataflows.clone() {
+                Some(dataflows) => pag.select_dataflows(&stream, dataflows),
+                None => pag,
+            };
+            let pag = select(untrimmed.trim_local());
+            let untrimmed = select(untrimmed);
             pag.inspect(|x| println!("{},", serde_json::to_string(x).unwrap()));
 
+            // edges are attributed to dataflows once the topology is complete
+            let collected = pag.exchange(|_| 0);
+            collected.inspect(move |x| summaries_sink.borrow_mut().update(x));
+
+            // progress messages aren't fully represented in the PAG
+            let analysis_events = if analyze_progress {
+                stream.peel_dataflows(dataflows.clone())
+                    .filter(|x| matches!(x.2, TimelyEvent::Progress(_)))
+                    .exchange(|_| 0)
+                    .concat(&topology_events)
+            } else {
+                topology_events.clone()
+            };
+
+            // analyses are run on the PAG collected at worker 0 as it is constructed
+            for analysis in analyses.iter() {
+                let path = format!("{}.json", analysis);
+                match analysis.as_str() {
+                    "scopes" => write_lines(&collected.analyze(&analysis_events, ScopeProfile::default()), &path, index),
+                    "waits" => write_lines(&collected.analyze(&analysis_events, WaitGraph::new(window)), &path, index),
+                    "processing" => write_lines(&collected.analyze(&analysis_events, ProcessingHistograms::new(window)), &path, index),
+                    "latency" => write_lines(&collected.analyze(&analysis_events, RemoteLatency::new(window)), &path, index),
+                    "outliers" => write_lines(&collected.analyze(&analysis_events, Outliers::new(window, top_k)), &path, index),
+                    "scheduling" => write_lines(&untrimmed.exchange(|_| 0).analyze(&analysis_events, SchedulingProfile::default()), &path, index),
+                    "progress" => write_lines(&collected.analyze(&analysis_events, ProgressOverhead::new(window)), &path, index),
+                    "parallelism" => {
+                        let parallelism = collected.analyze(&analysis_events, ParallelismProfile::new(window));
+                        write_csv(&parallelism, "parallelism.csv", ParallelismStep::CSV_HEADER, |w| w.steps.iter().map(|s| s.to_csv()).collect(), index);
+                        write_lines(&parallelism, &path, index);
+                    }
+                    "utilization" => {
+                        let utilization = collected.analyze(&analysis_events, Utilization::new(window));
+                        write_csv(&utilization, "utilization.csv", WorkerUtilization::CSV_HEADER, WorkerUtilization::to_csv, index);
+                        write_lines(&utilization, &path, index);
+                    }
+                    "critical" => write_lines(&untrimmed.exchange(|_| 0).analyze(&analysis_events, CriticalPaths::new(window)), &path, index),
+                    "whatif" => write_lines(&untrimmed.exchange(|_| 0).analyze(&analysis_events, WhatIf::new(scenarios.clone(), window)), &path, index),
+                    other => if index == 0 {
+                        eprintln!("unknown analysis: {}", other);
+                    }
+                }
+            }
+
             // TODO: PAG viz via socket
             // TODO: feature parity
         });
+
+        while worker.step() { }
+
+        if worker.index() == 0 {
+            let topology = topology.borrow();
+            std::fs::write("topology.json", topology.to_json()).expect("couldn't write topology");
+            std::fs::write("topology.dot", topology.to_dot()).expect("couldn't write topology");
+
+            write_json("summary.json", &summaries.borrow().report(&topology));
+        }
     }).unwrap();
 }
 
 
+/// Writes every result of an analysis as a JSON line to `path`.
+/// Since analyses run at worker 0, other workers don't write anything.
+fn write_lines<S: Scope, D: Data + Serialize>(results: &Stream<S, D>, path: &str, index: usize) {
+    let mut file = if index == 0 {
+        Some(LineWriter::new(File::create(path).unwrap_or_else(|_| panic!("couldn't create {}", path))))
+    } else {
+        None
+    };
+
+    let path = path.to_string();
+    results.inspect(move |x| if let Some(file) = file.as_mut() {
+        writeln!(file, "{}", serde_json::to_string(x).unwrap()).unwrap_or_else(|_| panic!("couldn't write {}", path));
+    });
+}
+
+/// Writes the CSV rows of every result of an analysis to `path`, after `header`.
+fn write_csv<S, D, F>(results: &Stream<S, D>, path: &str, header: &str, rows: F, index: usize)
+where S: Scope, D: Data, F: Fn(&D) -> Vec<String> + 'static
+{
+    let mut file = if index == 0 {
+        let mut file = LineWriter::new(File::create(path).unwrap_or_else(|_| panic!("couldn't create {}", path)));
+        writeln!(file, "{}", header).unwrap_or_else(|_| panic!("couldn't write {}", path));
+        Some(file)
+    } else {
+        None
+    };
+
+    let path = path.to_string();
+    results.inspect(move |x| if let Some(file) = file.as_mut() {
+        for row in rows(x) {
+            writeln!(file, "{}", row).unwrap_or_else(|_| panic!("couldn't write {}", path));
+        }
+    });
+}
+
+fn write_json<T: Serialize>(path: &str, value: &T) {
+    let json = serde_json::to_string(value).unwrap();
+    std::fs::write(path, json).unwrap_or_else(|_| panic!("couldn't write {}", path));
+}
+
 fn make_replay_source(source_peers: usize, from_file: bool) -> ReplaySource {
     if from_file {
         println!("Reading from {} *.dump files", source_peers);
@@ -0,0 +1,140 @@
+//! Single-threaded PAG construction without a Timely runtime.
+//! Reuses the exact logic of the dataflow operators in `pag`, so it
+//! can double as a reference implementation for them.
+
+#![deny(missing_docs)]
+
+use std::collections::{HashMap, BTreeSet};
+
+use crate::{Event, PagEdge};
+use crate::pag::{Peeler, LocalEdges, Trimmer, RemoteKey, remote_key, build_remote_edge, selected};
+use crate::topology::Topology;
+
+/// Constructs the PAG from log events synchronously.
+/// Events of each worker have to be provided in the order they were logged,
+/// e.g. as read from that worker's log. Like the dataflow pipeline, this
+/// returns local edges (optionally trimmed) followed by remote edges.
+pub fn build_pag<I: IntoIterator<Item = Event>>(events: I, trim: bool) -> Vec<PagEdge> {
+    build_dataflows_pag(events, trim, None)
+}
+
+/// Like `build_pag`, but restricted to activities of the given `dataflows`
+/// (cf. `PagBuilder::dataflows`), or all dataflows if `None`.
+pub fn build_dataflows_pag<I>(events: I, trim: bool, dataflows: Option<BTreeSet<usize>>) -> Vec<PagEdge>
+where I: IntoIterator<Item = Event>
+{
+    let mut pag = IncrementalPag::new(trim);
+    let mut topology = Topology::default();
+    let (mut local, remote): (Vec<_>, Vec<_>) = events.into_iter()
+        .inspect(|x| topology.update(x))
+        .flat_map(|x| pag.push(x))
+        .partition(|e| e.src.wid == e.dst.wid);
+
+    local.extend(remote);
+    match dataflows {
+        Some(dataflows) => local.into_iter().filter(|e| selected(&topology, &dataflows, e)).collect(),
+        None => local,
+    }
+}
+
+/// Constructs the PAG event by event, cf. `build_pag`. Only the state needed to
+/// complete further edges is kept, s.t. events can be dropped once pushed.
+pub(crate) struct IncrementalPag {
+    peeler: Peeler,
+    local_edges: LocalEdges,
+    trimmer: Option<Trimmer>,
+    sent: HashMap<RemoteKey, Vec<Event>>,
+    received: HashMap<RemoteKey, Vec<Event>>,
+}
+
+impl IncrementalPag {
+    /// Starts PAG construction, optionally trimming local edges.
+    pub(crate) fn new(trim: bool) -> Self {
+        IncrementalPag {
+            peeler: Peeler::default(),
+            local_edges: LocalEdges::default(),
+            trimmer: if trim { Some(Trimmer::default()) } else { None },
+            sent: HashMap::new(),
+            received: HashMap::new(),
+        }
+    }
+
+    /// Pushes the next event, returning the edges it completes.
+    pub(crate) fn push(&mut self, event: Event) -> Vec<PagEdge> {
+        let mut edges = Vec::new();
+        let event = match self.peeler.peel(event) {
+            Some(event) => event,
+            None => return edges,
+        };
+
+        // same hash join as `JoinEdges`, just without exchanging data
+        if let Some((key, is_send)) = remote_key(&event) {
+            let (this, other) = if is_send { (&mut self.sent, &mut self.received) } else { (&mut self.received, &mut self.sent) };
+            let matched = other.get(&key).map(|values| values.iter().map(|x| {
+                if is_send { build_remote_edge(&event, x) } else { build_remote_edge(x, &event) }
+            }).collect::<Vec<_>>()).unwrap_or_default();
+
+            // a data message is received exactly once, whereas progress is broadcast to all workers
+            if key.1.is_some() && !matched.is_empty() {
+                other.remove(&key);
+            } else {
+                this.entry(key).or_default().push(event.clone());
+            }
+            edges.extend(matched);
+        }
+
+        if let Some(edge) = self.local_edges.push(event) {
+            match self.trimmer.as_mut() {
+                Some(trimmer) => edges.extend(trimmer.push(edge)),
+                None => edges.push(edge),
+            }
+        }
+
+        edges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{exchange_events, two_dataflow_events, dataflow_pag, dataflows_pag, sorted};
+    use crate::EdgeKind;
+
+    #[test]
+    fn same_edges_as_dataflow() {
+        let events = exchange_events(2, 10);
+
+        for trim in &[false, true] {
+            let offline = sorted(build_pag(events.clone(), *trim));
+            let dataflow = sorted(dataflow_pag(events.clone(), *trim));
+            assert!(!offline.is_empty());
+            assert_eq!(offline, dataflow);
+        }
+    }
+
+    #[test]
+    fn contains_remote_edges() {
+        let pag = build_pag(exchange_events(2, 10), true);
+        assert!(pag.iter().any(|e| e.src.wid != e.dst.wid));
+        assert!(pag.iter().all(|e| e.src.wid != e.dst.wid || e.src.t <= e.dst.t));
+    }
+
+    #[test]
+    fn same_dataflows_as_dataflow() {
+        let events = two_dataflow_events(2, 5);
+        let topology = Topology::from_events(events.iter());
+        let dataflows: BTreeSet<usize> = Some(1).into_iter().collect();
+
+        for trim in &[false, true] {
+            let offline = sorted(build_dataflows_pag(events.clone(), *trim, Some(dataflows.clone())));
+            let dataflow = sorted(dataflows_pag(events.clone(), *trim, Some(dataflows.clone())));
+            assert_eq!(offline, dataflow);
+
+            // constructed from all dataflows, s.t. dataflow 0 isn't mistaken for waiting or busy time
+            let all = sorted(build_pag(events.clone(), *trim));
+            assert_eq!(offline, all.into_iter().filter(|e| topology.dataflow_of(&e.edge_type) != Some(0)).collect::<Vec<_>>());
+            assert!(offline.iter().any(|e| topology.dataflow_of(&e.edge_type) == Some(1)));
+            assert!(offline.iter().any(|e| e.edge_type.kind() == EdgeKind::Busy));
+        }
+    }
+}
@@ -6,22 +6,89 @@ use timely::dataflow::operators::map::Map;
 use timely::dataflow::operators::generic::operator::Operator;
 use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::Capability;
 use timely::logging::StartStop;
-use timely::logging::TimelyEvent::{Messages, Progress, Schedule, Operates};
+use timely::logging::TimelyEvent::{Messages, Progress, Schedule, Operates, Channels, Shutdown, Park, GuardedMessage, GuardedProgress};
+use timely::logging::ParkEvent;
+use timely::dataflow::operators::inspect::Inspect;
+use timely::dataflow::operators::concat::Concat;
 use timely::Data;
 
-use st2::{PagNode, PagEdge, Event, EdgeType};
+use crate::{PagNode, PagEdge, Event, EdgeType, WaitingCause};
+use crate::topology::Topology;
 
-use std::collections::{HashMap, BTreeSet};
+use std::collections::{HashMap, BTreeSet, VecDeque};
 use std::hash::Hash;
 
 use serde::{Serialize, Deserialize};
 
 
+/// Wires up the PAG construction pipeline in a user-provided scope:
+/// source → peel → local / remote edges → (optional) trim → (optional) select dataflows → sink
+pub struct PagBuilder<S: Scope> {
+    source: Stream<S, Event>,
+    trim: bool,
+    dataflows: Option<BTreeSet<usize>>,
+}
+
+impl<S: Scope> PagBuilder<S> {
+    /// Starts PAG construction from a stream of Timely log events,
+    /// e.g. one obtained by replaying `tdiag_connect` readers into `scope`.
+    pub fn new(source: &Stream<S, Event>) -> Self {
+        PagBuilder {
+            source: source.clone(),
+            trim: true,
+            dataflows: None,
+        }
+    }
+
+    /// Whether local edges should be trimmed (default: `true`)
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Restricts the PAG to activities of the given dataflows, identified by
+    /// the first element of their operators' addresses (default: all dataflows).
+    /// The PAG is still constructed from the activity of all dataflows, s.t. processing of
+    /// other dataflows isn't mistaken for waiting or busy time. Edges attributed to other
+    /// dataflows are dropped afterwards, cf. `SelectDataflows`.
+    pub fn dataflows<I: IntoIterator<Item = usize>>(mut self, dataflows: I) -> Self {
+        self.dataflows = Some(dataflows.into_iter().collect());
+        self
+    }
+
+    /// Constructs the PAG, returning a stream of its edges.
+    pub fn build(self) -> Stream<S, PagEdge> {
+        let peeled = self.source.peel();
+
+        let local_edges = if self.trim {
+            peeled.local_edges().trim_local()
+        } else {
+            peeled.local_edges()
+        };
+        let pag = local_edges.concat(&peeled.remote_edges());
+
+        match self.dataflows {
+            Some(dataflows) => pag.select_dataflows(&self.source, dataflows),
+            None => pag,
+        }
+    }
+
+    /// Constructs the PAG and hands every edge to `sink`.
+    pub fn sink<F: FnMut(&PagEdge) + 'static>(self, sink: F) -> Stream<S, PagEdge> {
+        self.build().inspect(sink)
+    }
+}
+
 /// Trait subsuming the steps to construct the PAG
 pub trait Pag<S: Scope> {
     /// Peel outer scopes
-    fn peel(&self) -> Stream<S, Event>;
+    fn peel(&self) -> Stream<S, Event> {
+        self.peel_dataflows(None)
+    }
+    /// Peel outer scopes and, if provided, all dataflows not contained in `dataflows`
+    fn peel_dataflows(&self, dataflows: Option<BTreeSet<usize>>) -> Stream<S, Event>;
     /// Create local edges
     fn local_edges(&self) -> Stream<S, PagEdge>;
     /// Create remote edges
@@ -29,37 +96,18 @@ pub trait Pag<S: Scope> {
 }
 
 impl<S: Scope> Pag<S> for Stream<S, Event> {
-    fn peel(&self) -> Stream<S, Event> {
+    fn peel_dataflows(&self, dataflows: Option<BTreeSet<usize>>) -> Stream<S, Event> {
         self.unary(Pipeline, "Peel", move |_, _| {
             let mut vector = Vec::new();
-            let mut outer_operates = BTreeSet::new();
-            let mut ids_to_addrs = HashMap::new();
+            let mut peeler = Peeler::new(dataflows);
 
             move |input, output| {
                 input.for_each(|cap, data| {
                     data.swap(&mut vector);
-                    for (t, wid, x) in vector.drain(..) {
-                        match x {
-                            Operates(e) => {
-                                let mut addr = e.addr.clone();
-                                addr.pop();
-                                outer_operates.insert(addr);
-
-                                ids_to_addrs.insert(e.id, e.addr);
-                            }
-                            Schedule(ref e) => {
-                                let addr = ids_to_addrs.get(&e.id).expect("operates went wrong");
-                                if !outer_operates.contains(addr) {
-                                    output.session(&cap).give((t, wid, x));
-                                }
-                            }
-                            Progress(ref e) if e.source != wid || e.is_send => {
-                                output.session(&cap).give((t, wid, x));
-                            }
-                            Messages(ref _e) => {
-                                output.session(&cap).give((t, wid, x));
-                            }
-                            _ => { /* filters out all events we don't need */ }
+                    let mut session = output.session(&cap);
+                    for x in vector.drain(..) {
+                        if let Some(x) = peeler.peel(x) {
+                            session.give(x);
                         }
                     }
                 });
@@ -67,48 +115,34 @@ impl<S: Scope> Pag<S> for Stream<S, Event> {
         })
     }
 
-    /// A "stateful map" operator that finds neighboring local events and
-    /// creates a local PAG edge from them.
-    /// We need to keep track of two events while looking at a third:
-    /// `prev2` and `prev` are joined together to form an edge.
-    /// We also need to peek at the next event: Data messages that cause
-    /// waiting activities are received only after prev2 and prev have
-    /// already occurred. If we wouldn't peek, we'd miss this causality.
+    /// Edges are emitted at the capability of their source event, s.t. the
+    /// frontier of the output tells which local edges are complete.
     fn local_edges(&self) -> Stream<S, PagEdge> {
-        self.unary(Pipeline, "Local Edges", move |_, _| {
+        self.unary_frontier(Pipeline, "Local Edges", move |_, _| {
             let mut vector = Vec::new();
-            let mut buffer: HashMap<usize, Event> = HashMap::new();
-            let mut buffer2: HashMap<usize, Event> = HashMap::new();
-            let mut oids: HashMap<usize, Option<usize>> = HashMap::new();
+            let mut local_edges = LocalEdges::default();
+            // capabilities of the events buffered per worker
+            let mut caps: HashMap<usize, VecDeque<Capability<S::Timestamp>>> = HashMap::new();
 
             move |input, output| {
                 input.for_each(|cap, data| {
                     data.swap(&mut vector);
-
+                    let cap = cap.retain();
                     for curr in vector.drain(..) {
-                        let (t, wid, _x) = &curr;
-
-                        if let Some(prev) = buffer.remove(&wid) {
-                            let (prev_t, _prev_wid, _prev_x) = &prev;
-                            assert!(t >= prev_t);
-
-                            if let Some(prev2) = buffer2.remove(&wid) {
-                                let (prev2_t, _prev2_wid, _prev2_x) = &prev2;
-                                assert!(prev_t >= prev2_t);
-
-                                let oid = oids.entry(*wid).or_insert(None);
-                                let edge = build_local_edge(&prev2, &prev, &curr, oid);
-                                output.session(&cap).give(edge);
-                            }
+                        let caps = caps.entry(curr.1).or_default();
+                        caps.push_back(cap.clone());
 
-                            // move prev -> prev2
-                            buffer2.insert(*wid, prev);
+                        if let Some(edge) = local_edges.push(curr) {
+                            let src_cap = caps.pop_front().expect("edge without capability");
+                            output.session(&src_cap).give(edge);
                         }
-
-                        // move curr -> prev
-                        buffer.insert(*wid, curr);
                     }
                 });
+
+                // the last events of each worker don't complete any edge
+                if input.frontier().frontier().is_empty() {
+                    caps.clear();
+                }
             }
         })
     }
@@ -116,51 +150,229 @@ impl<S: Scope> Pag<S> for Stream<S, Event> {
     /// Uses a Timely join to create remote edges from log events.
     fn remote_edges(&self) -> Stream<S, PagEdge> {
         let sent = self
-            .flat_map(|(t, wid, x)| match x {
-                Progress(ref e) if e.is_send => Some(((e.source, None, e.seq_no, e.channel), (t, wid, x))),
-                Messages(ref e) if e.is_send && e.source != e.target => Some(((e.source, Some(e.target), e.seq_no, e.channel), (t, wid, x))),
+            .flat_map(|x| match remote_key(&x) {
+                Some((key, true)) => Some((key, x)),
                 _ => None
             });
 
         let received = self
-            .flat_map(|(t, wid, x)| match x {
-                Progress(ref e) if !e.is_send => Some(((e.source, None, e.seq_no, e.channel), (t, wid, x))),
-                Messages(ref e) if !e.is_send && e.source != e.target => Some(((e.source, Some(e.target), e.seq_no, e.channel), (t, wid, x))),
+            .flat_map(|x| match remote_key(&x) {
+                Some((key, false)) => Some((key, x)),
                 _ => None
             });
 
         sent
             .join_edges(&received)
-            .map(|((from_t, from_wid, from_x), (to_t, to_wid, _to_x))| {
-                let edge_type = match from_x {
-                    Progress(ref _e) => EdgeType::Progress,
-                    Messages(ref e) => EdgeType::Data(e.length),
-                    _ => unreachable!()
-                };
+            .map(|(from, to)| build_remote_edge(&from, &to))
+    }
+}
+
+/// Filters the event stream down to the events relevant for PAG construction.
+/// `Schedule` events of operators that contain other operators (i.e., outer
+/// scopes) are peeled off, so that only leaf operators remain.
+/// Optionally, only events belonging to a set of dataflows are kept.
+/// Operators are tracked per worker and released once they shut down.
+#[derive(Default)]
+pub(crate) struct Peeler {
+    outer_operates: HashMap<usize, BTreeSet<Vec<usize>>>,
+    ids_to_addrs: HashMap<(usize, usize), Vec<usize>>,
+    channels_to_dataflows: HashMap<usize, usize>,
+    dataflows: Option<BTreeSet<usize>>,
+    /// whether the leaf operator last scheduled on a worker is kept
+    scheduled: HashMap<usize, bool>,
+}
+
+impl Peeler {
+    /// Creates a peeler keeping only events of `dataflows` (or all, if `None`).
+    pub(crate) fn new(dataflows: Option<BTreeSet<usize>>) -> Self {
+        Peeler {
+            dataflows,
+            ..Default::default()
+        }
+    }
+
+    /// Whether events of the dataflow are kept.
+    fn selected(&self, dataflow: Option<usize>) -> bool {
+        match (&self.dataflows, dataflow) {
+            (None, _) => true,
+            (Some(dataflows), Some(dataflow)) => dataflows.contains(&dataflow),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Returns the event if it should be kept.
+    pub(crate) fn peel(&mut self, event: Event) -> Option<Event> {
+        if self.keep(&event) {
+            Some(event)
+        } else {
+            None
+        }
+    }
 
-                PagEdge {
-                    src: PagNode { t: from_t, wid: from_wid },
-                    dst: PagNode { t: to_t, wid: to_wid },
-                    edge_type
+    /// Whether the event should be kept. `Operates`, `Channels` and `Shutdown`
+    /// events are never kept, but are used to determine which operators are
+    /// outer scopes and which dataflow a message belongs to.
+    pub(crate) fn keep(&mut self, event: &Event) -> bool {
+        let (_t, wid, x) = event;
+        match x {
+            Operates(e) => {
+                let mut addr = e.addr.clone();
+                addr.pop();
+                self.outer_operates.entry(*wid).or_default().insert(addr);
+
+                self.ids_to_addrs.insert((*wid, e.id), e.addr.clone());
+                false
+            }
+            Shutdown(e) => {
+                if let Some(addr) = self.ids_to_addrs.remove(&(*wid, e.id)) {
+                    // operators inside a scope aren't shut down on their own when the scope is
+                    self.ids_to_addrs.retain(|(w, _), a| w != wid || !a.starts_with(&addr));
+                    if let Some(outer) = self.outer_operates.get_mut(wid) {
+                        outer.retain(|a| !a.starts_with(&addr));
+                    }
+                }
+                false
+            }
+            Channels(e) => {
+                if let Some(dataflow) = e.scope_addr.first() {
+                    self.channels_to_dataflows.insert(e.id, *dataflow);
                 }
-            })
+                false
+            }
+            Schedule(e) => {
+                // Operators without an `Operates` event (e.g. if logging was set up only after
+                // the dataflow was constructed) are treated as leaves of an unknown dataflow.
+                let addr = self.ids_to_addrs.get(&(*wid, e.id));
+                let outer = addr.is_some_and(|addr| self.outer_operates.get(wid).is_some_and(|outer| outer.contains(addr)));
+                if outer {
+                    false
+                } else {
+                    let selected = self.selected(addr.and_then(|addr| addr.first().copied()));
+                    self.scheduled.insert(*wid, selected);
+                    selected
+                }
+            }
+            Progress(e) if e.source != *wid || e.is_send => self.selected(e.addr.first().copied()),
+            Messages(e) => {
+                self.dataflows.is_none() || self.selected(self.channels_to_dataflows.get(&e.channel).copied())
+            }
+            // parking affects all dataflows of a worker
+            Park(_) => true,
+            // guards are only entered by the currently scheduled (leaf) operator
+            GuardedMessage(_) | GuardedProgress(_) => self.scheduled.get(wid).copied().unwrap_or(true),
+            _ => false /* filters out all events we don't need */
+        }
+    }
+}
+
+/// A "stateful map" that finds neighboring local events and
+/// creates a local PAG edge from them.
+/// We need to keep track of two events while looking at a third:
+/// `prev2` and `prev` are joined together to form an edge.
+/// We also need to peek at the next event: Data messages that cause
+/// waiting activities are received only after prev2 and prev have
+/// already occurred. If we wouldn't peek, we'd miss this causality.
+#[derive(Default)]
+pub(crate) struct LocalEdges {
+    buffer: HashMap<usize, Event>,
+    buffer2: HashMap<usize, Event>,
+    oids: HashMap<usize, Option<usize>>,
+    /// guards a worker currently is in, innermost last
+    guards: HashMap<usize, Vec<Guard>>,
+}
+
+/// Communication guard of an operator
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Guard {
+    /// handling received messages
+    Message,
+    /// handling progress notifications
+    Progress,
+}
+
+impl LocalEdges {
+    /// Pushes the next event of a worker, returning the edge it completes (if any).
+    pub(crate) fn push(&mut self, curr: Event) -> Option<PagEdge> {
+        let (t, wid, _x) = &curr;
+        let mut edge = None;
+
+        if let Some(prev) = self.buffer.remove(wid) {
+            let (prev_t, _prev_wid, _prev_x) = &prev;
+            assert!(t >= prev_t);
+
+            if let Some(prev2) = self.buffer2.remove(wid) {
+                let (prev2_t, _prev2_wid, _prev2_x) = &prev2;
+                assert!(prev_t >= prev2_t);
+
+                let oid = self.oids.entry(*wid).or_insert(None);
+                let guards = self.guards.entry(*wid).or_default();
+                edge = Some(build_local_edge(&prev2, &prev, &curr, oid, guards));
+            }
+
+            // move prev -> prev2
+            self.buffer2.insert(*wid, prev);
+        }
+
+        // move curr -> prev
+        self.buffer.insert(*wid, curr);
+
+        edge
+    }
+}
+
+/// Key under which a remote send and its corresponding receive are joined,
+/// together with whether the event is the sending side.
+pub(crate) type RemoteKey = (usize, Option<usize>, usize, usize);
+
+/// Returns the join key of a remote event, or `None` if the event is local.
+pub(crate) fn remote_key(event: &Event) -> Option<(RemoteKey, bool)> {
+    match &event.2 {
+        Progress(ref e) => Some(((e.source, None, e.seq_no, e.channel), e.is_send)),
+        Messages(ref e) if e.source != e.target => Some(((e.source, Some(e.target), e.seq_no, e.channel), e.is_send)),
+        _ => None
+    }
+}
+
+/// Builds a remote edge from a send event and its matching receive event.
+pub(crate) fn build_remote_edge(from: &Event, to: &Event) -> PagEdge {
+    let (from_t, from_wid, from_x) = from;
+    let (to_t, to_wid, _to_x) = to;
+
+    let edge_type = match from_x {
+        Progress(ref _e) => EdgeType::Progress,
+        Messages(ref e) => EdgeType::Data { length: e.length, channel: e.channel },
+        _ => unreachable!()
+    };
+
+    PagEdge {
+        src: PagNode { t: *from_t, wid: *from_wid },
+        dst: PagNode { t: *to_t, wid: *to_wid },
+        edge_type
     }
 }
 
 /// Builds a local edge from prev and curr, peeking at next to determine whether
-/// the edge is a waiting activity.
-fn build_local_edge(prev: &Event, curr: &Event, next: &Event, oid: &mut Option<usize>) -> PagEdge {
-    use EdgeType::{Processing, Waiting, Busy, Spinning};
+/// the edge is a waiting activity. Communication guards are entered by the scheduled
+/// operator, so its processing within them is attributed to the innermost guard.
+fn build_local_edge(prev: &Event, curr: &Event, next: &Event, oid: &mut Option<usize>, guards: &mut Vec<Guard>) -> PagEdge {
+    use EdgeType::{Processing, Waiting, Busy, Spinning, Parked};
+    use WaitingCause::{Data, Input};
 
     let (prev_t, prev_wid, prev_x) = prev;
     let (t, wid, x) = curr;
     let (_next_t, next_wid, next_x) = next;
     assert!(*prev_wid == *wid && *wid == *next_wid);
 
+    match prev_x {
+        GuardedMessage(p) if p.is_start => guards.push(Guard::Message),
+        GuardedProgress(p) if p.is_start => guards.push(Guard::Progress),
+        _ => {}
+    }
+
     let mut edge_type = match (prev_x, x) {
         (_, Progress(r)) if !r.is_send => {
             assert!(r.source != *wid);
-            Waiting
+            Waiting(WaitingCause::Progress)
         },
         (Schedule(p), Schedule(r)) if p.start_stop == StartStop::Start && r.start_stop == StartStop::Stop => Spinning(p.id),
         (Schedule(p), _) if p.start_stop == StartStop::Start => {
@@ -174,23 +386,40 @@ fn build_local_edge(prev: &Event, curr: &Event, next: &Event, oid: &mut Option<u
         }
         (Messages(p), _) if p.is_send => Processing { oid: *oid, send: Some(p.length), recv: None },
         (Messages(p), _) if !p.is_send => Processing { oid: *oid, send: None, recv: Some(p.length) },
+        // guards are entered and left while the operator is processing
+        (GuardedMessage(_), _) | (GuardedProgress(_), _) => Processing { oid: *oid, send: None, recv: None },
+        _ if !guards.is_empty() => Processing { oid: *oid, send: None, recv: None },
+        (Park(ParkEvent::Park(_)), Park(ParkEvent::Unpark)) => Parked,
         _ => Busy,
     };
 
-    // waiting on data message
+    if let Processing { oid, send, recv } = edge_type {
+        match guards.last() {
+            Some(Guard::Message) => edge_type = EdgeType::GuardedMessage { oid, send, recv },
+            Some(Guard::Progress) => edge_type = EdgeType::GuardedProgress { oid, send, recv },
+            None => {}
+        }
+    }
+
     if edge_type == Busy {
-        if let (Schedule(_), Messages(m)) = (x, next_x) {
-            if m.source != m.target {
-                edge_type = Waiting;
-            }
+        match (x, next_x) {
+            // waiting on data message
+            (Schedule(_), Messages(m)) if m.source != m.target => edge_type = Waiting(Data),
+            // waiting on input, sent from outside any operator (e.g. by an `InputHandle`)
+            (Messages(m), _) if m.is_send && oid.is_none() => edge_type = Waiting(Input),
+            _ => {}
         }
     }
 
-    // Reset oid if scheduling ended.
-    if let Schedule(r) = x {
-        if r.start_stop == StartStop::Stop {
+    // Reset oid if scheduling ended, and leave the innermost guard if it ended.
+    match x {
+        Schedule(r) if r.start_stop == StartStop::Stop => {
             *oid = None;
+            guards.clear();
         }
+        GuardedMessage(r) if !r.is_start => { guards.pop(); }
+        GuardedProgress(r) if !r.is_start => { guards.pop(); }
+        _ => {}
     }
 
     PagEdge {
@@ -208,75 +437,162 @@ fn build_local_edge(prev: &Event, curr: &Event, next: &Event, oid: &mut Option<u
 /// This operator merges these edges back together, s.t. it is easier to interpret.
 /// Trimming is optional --- the PAG is correct even without it.
 pub trait TrimPag<S: Scope> {
+    /// Merge consecutive local edges of the same activity. Remote edges are passed through.
     fn trim_local(&self) -> Stream<S, PagEdge>;
 }
 
 impl<S: Scope> TrimPag<S> for Stream<S, PagEdge> {
+    /// Like local edges, trimmed edges are emitted at the capability of their source.
     fn trim_local(&self) -> Stream<S, PagEdge> {
-        use st2::EdgeType::{Processing, Waiting, Busy, Spinning, Data, Progress};
-
-        self.unary(Pipeline, "Trim", move |_, _| {
+        self.unary_frontier(Pipeline, "Trim", move |_, _| {
             let mut vector = Vec::new();
-            let mut first_edge: HashMap<usize, PagEdge> = HashMap::new();
+            let mut trimmer = Trimmer::default();
+            // capability of the edge currently extended per worker
+            let mut caps: HashMap<usize, Capability<S::Timestamp>> = HashMap::new();
 
             move |input, output| {
                 input.for_each(|cap, data| {
                     data.swap(&mut vector);
-
-                    for mut edge in vector.drain(..) {
+                    let cap = cap.retain();
+                    for edge in vector.drain(..) {
                         let wid = edge.src.wid;
-                        if let Some(mut first) = first_edge.remove(&wid) {
-                            if edge.edge_type == Busy && first.edge_type != Waiting {
-                                first.dst = edge.dst;
-                                first_edge.insert(wid, first);
-                            } else if first.edge_type == Busy {
-                                edge.src = first.src;
-                                first_edge.insert(wid, edge);
-                            } else if edge.edge_type == first.edge_type {
-                                first.dst = edge.dst;
-
-                                first.edge_type = match (first.edge_type, edge.edge_type) {
-                                    (Processing { send: f_send, recv: f_recv, oid},
-                                     Processing { send: e_send, recv: e_recv, ..}) => {
-                                        let send = match (f_send, e_send) {
-                                            (None, None) => None,
-                                            (Some(x), None) => Some(x),
-                                            (None, Some(x)) => Some(x),
-                                            (Some(x), Some(y)) => Some(x + y)
-                                        };
-
-                                        let recv = match (f_recv, e_recv) {
-                                            (None, None) => None,
-                                            (Some(x), None) => Some(x),
-                                            (None, Some(x)) => Some(x),
-                                            (Some(x), Some(y)) => Some(x + y)
-                                        };
-
-                                        Processing { oid: oid, send, recv }
-                                    }
-                                    (Spinning(f), Spinning(_)) => Spinning(f),
-                                    (Progress, Progress) => Progress,
-                                    (Data(f), Data(e)) => Data(f + e),
-                                    (Waiting, Waiting) => Waiting,
-                                    (Busy, Busy) => Busy,
-                                    _ => unreachable!()
-                                };
-
-                                first_edge.insert(wid, first);
-                            } else {
-                                output.session(&cap).give(first);
-                                first_edge.insert(wid, edge);
-                            }
+                        if wid != edge.dst.wid {
+                            output.session(&cap).give(edge);
+                        } else if let Some(trimmed) = trimmer.push(edge) {
+                            let src_cap = caps.insert(wid, cap.clone()).expect("edge without capability");
+                            output.session(&src_cap).give(trimmed);
                         } else {
-                            first_edge.insert(wid, edge);
+                            caps.entry(wid).or_insert_with(|| cap.clone());
                         }
                     }
-                })
+                });
+
+                // the last edge of each worker is never completed
+                if input.frontier().frontier().is_empty() {
+                    caps.clear();
+                }
+            }
+        })
+    }
+}
+
+/// Merges consecutive local edges per worker, cf. `TrimPag`.
+#[derive(Default)]
+pub(crate) struct Trimmer {
+    first_edge: HashMap<usize, PagEdge>,
+}
+
+impl Trimmer {
+    /// Pushes the next local edge of a worker, returning the previous
+    /// (merged) edge once it can't be extended anymore.
+    pub(crate) fn push(&mut self, mut edge: PagEdge) -> Option<PagEdge> {
+        use crate::EdgeType::{Processing, Waiting, Busy, Spinning, Data, Progress, Parked, GuardedMessage, GuardedProgress};
+
+        let wid = edge.src.wid;
+        if let Some(mut first) = self.first_edge.remove(&wid) {
+            // parked time is kept separate from surrounding busy time
+            if edge.edge_type == Busy && !first.edge_type.kind().is_waiting() && first.edge_type != Parked {
+                first.dst = edge.dst;
+                self.first_edge.insert(wid, first);
+            } else if first.edge_type == Busy && edge.edge_type != Parked {
+                edge.src = first.src;
+                self.first_edge.insert(wid, edge);
+            } else if edge.edge_type.kind() == first.edge_type.kind()
+                   && edge.edge_type.oid() == first.edge_type.oid()
+                   && edge.edge_type.channel() == first.edge_type.channel() {
+                first.dst = edge.dst;
+
+                first.edge_type = match (first.edge_type, edge.edge_type) {
+                    (Processing { send: f_send, recv: f_recv, oid},
+                     Processing { send: e_send, recv: e_recv, ..}) => {
+                        Processing { oid, send: sum(f_send, e_send), recv: sum(f_recv, e_recv) }
+                    }
+                    (GuardedMessage { send: f_send, recv: f_recv, oid},
+                     GuardedMessage { send: e_send, recv: e_recv, ..}) => {
+                        GuardedMessage { oid, send: sum(f_send, e_send), recv: sum(f_recv, e_recv) }
+                    }
+                    (GuardedProgress { send: f_send, recv: f_recv, oid},
+                     GuardedProgress { send: e_send, recv: e_recv, ..}) => {
+                        GuardedProgress { oid, send: sum(f_send, e_send), recv: sum(f_recv, e_recv) }
+                    }
+                    (Spinning(f), Spinning(_)) => Spinning(f),
+                    (Progress, Progress) => Progress,
+                    (Data { length: f, channel }, Data { length: e, .. }) => Data { length: f + e, channel },
+                    (Waiting(c), Waiting(_)) => Waiting(c),
+                    (Busy, Busy) => Busy,
+                    (Parked, Parked) => Parked,
+                    _ => unreachable!()
+                };
+
+                self.first_edge.insert(wid, first);
+            } else {
+                self.first_edge.insert(wid, edge);
+                return Some(first);
+            }
+        } else {
+            self.first_edge.insert(wid, edge);
+        }
+
+        None
+    }
+}
+
+/// Sum of two optional counts, `None` if neither is present
+fn sum(x: Option<usize>, y: Option<usize>) -> Option<usize> {
+    match (x, y) {
+        (Some(x), Some(y)) => Some(x + y),
+        (x, y) => x.or(y),
+    }
+}
+
+
+/// Restricts a PAG to the activity of some dataflows, cf. `PagBuilder::dataflows`.
+pub trait SelectDataflows<S: Scope> {
+    /// Keeps edges attributed to one of `dataflows` (cf. `Topology::dataflow_of`) and
+    /// edges not attributed to any dataflow, e.g. waiting. Dataflows are looked up in
+    /// `events`, which have to contain the `Operates` and `Channels` events of the workers
+    /// whose edges are selected (e.g. the events the PAG is constructed from).
+    fn select_dataflows(&self, events: &Stream<S, Event>, dataflows: BTreeSet<usize>) -> Stream<S, PagEdge>;
+}
+
+impl<S: Scope> SelectDataflows<S> for Stream<S, PagEdge> {
+    /// Events are incorporated before edges, as they arrive no later than the edges constructed from them.
+    fn select_dataflows(&self, events: &Stream<S, Event>, dataflows: BTreeSet<usize>) -> Stream<S, PagEdge> {
+        self.binary(events, Pipeline, Pipeline, "Select Dataflows", move |_, _| {
+            let mut topology = Topology::default();
+            let mut edges_vector = Vec::new();
+            let mut events_vector = Vec::new();
+
+            move |edges, events, output| {
+                events.for_each(|_, data| {
+                    data.swap(&mut events_vector);
+                    for event in events_vector.drain(..) {
+                        topology.update(&event);
+                    }
+                });
+
+                edges.for_each(|cap, data| {
+                    data.swap(&mut edges_vector);
+                    let mut session = output.session(&cap);
+                    for edge in edges_vector.drain(..) {
+                        if selected(&topology, &dataflows, &edge) {
+                            session.give(edge);
+                        }
+                    }
+                });
             }
         })
     }
 }
 
+/// Whether an edge is attributed to one of `dataflows`, or to none at all
+pub(crate) fn selected(topology: &Topology, dataflows: &BTreeSet<usize>, edge: &PagEdge) -> bool {
+    match topology.dataflow_of(&edge.edge_type) {
+        Some(dataflow) => dataflows.contains(&dataflow),
+        None => true,
+    }
+}
+
 
 /// Naive Timely join operator specialized for joining log events together
 trait JoinEdges<S: Scope, D> where D: Data + Hash + Eq + Send + Sync + Serialize + for<'a>Deserialize<'a> {
@@ -303,7 +619,7 @@ where D: Data + Hash + Eq + Send + Sync + Serialize + for<'a>Deserialize<'a>
         });
 
         // @TODO: in this join implementation, state continually grows.
-        self.binary(&other, exchange, exchange2, "HashJoin", |_capability, _info| {
+        self.binary(other, exchange, exchange2, "HashJoin", |_capability, _info| {
             let mut map1 = HashMap::new();
             let mut map2 = HashMap::<_, Vec<Event>>::new();
 
@@ -343,3 +659,249 @@ where D: Data + Hash + Eq + Send + Sync + Serialize + for<'a>Deserialize<'a>
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{edge, exchange_events, processing};
+    use crate::offline::build_pag;
+    use crate::EdgeType::{Processing, Busy, Parked, Spinning, Waiting, GuardedMessage, GuardedProgress};
+
+    use std::time::Duration;
+    use timely::logging::{ScheduleEvent, OperatesEvent, MessagesEvent, ProgressEvent, TimelyEvent};
+    use timely::logging::{GuardedMessageEvent, GuardedProgressEvent};
+
+    fn schedule(id: usize) -> Event {
+        (Duration::from_nanos(1), 0, start(id))
+    }
+
+    #[test]
+    fn peel_keeps_unknown_operators() {
+        let mut peeler = Peeler::default();
+        assert!(peeler.keep(&schedule(7)));
+
+        // unknown operators don't belong to any selected dataflow
+        let mut peeler = Peeler::new(Some(vec![0].into_iter().collect()));
+        assert!(!peeler.keep(&schedule(7)));
+
+        let operates = OperatesEvent { id: 7, addr: vec![0, 1], name: "Map".to_string() };
+        assert!(!peeler.keep(&(Duration::from_nanos(0), 0, Operates(operates))));
+        assert!(peeler.keep(&schedule(7)));
+    }
+
+    #[test]
+    fn pag_without_operates() {
+        // e.g. logging set up after the dataflow was constructed
+        let events = exchange_events(2, 5).into_iter().filter(|x| !matches!(x.2, Operates(_)));
+        assert!(!build_pag(events, true).is_empty());
+    }
+
+    fn start(id: usize) -> TimelyEvent {
+        Schedule(ScheduleEvent { id, start_stop: StartStop::Start })
+    }
+
+    fn stop(id: usize) -> TimelyEvent {
+        Schedule(ScheduleEvent { id, start_stop: StartStop::Stop })
+    }
+
+    fn message(is_send: bool, source: usize, target: usize) -> TimelyEvent {
+        Messages(MessagesEvent { is_send, channel: 3, source, target, seq_no: 0, length: 4 })
+    }
+
+    fn progress(is_send: bool, source: usize) -> TimelyEvent {
+        Progress(ProgressEvent { is_send, source, channel: 5, seq_no: 0, addr: vec![0], messages: Vec::new(), internal: Vec::new() })
+    }
+
+    fn guard_message(is_start: bool) -> TimelyEvent {
+        GuardedMessage(GuardedMessageEvent { is_start })
+    }
+
+    fn guard_progress(is_start: bool) -> TimelyEvent {
+        GuardedProgress(GuardedProgressEvent { is_start })
+    }
+
+    fn park() -> TimelyEvent {
+        Park(ParkEvent::Park(None))
+    }
+
+    fn unpark() -> TimelyEvent {
+        Park(ParkEvent::Unpark)
+    }
+
+    /// Local edges of worker 0 logging `events` at 0ns, 1ns, ...
+    fn local(events: Vec<TimelyEvent>) -> Vec<PagEdge> {
+        let mut local_edges = LocalEdges::default();
+        events.into_iter().enumerate()
+            .filter_map(|(t, x)| local_edges.push((Duration::from_nanos(t as u64), 0, x)))
+            .collect()
+    }
+
+    #[test]
+    fn waiting_for_data() {
+        // the worker waits until it receives a remote message, and then schedules its recipient
+        let edges = local(vec![stop(1), start(2), message(false, 1, 0), stop(2)]);
+        assert_eq!(edges, vec![
+            edge((0, 0), (1, 0), Waiting(WaitingCause::Data)),
+            edge((1, 0), (2, 0), processing(2)),
+        ]);
+
+        // local messages don't unblock anything
+        let edges = local(vec![stop(1), start(2), message(false, 0, 0), stop(2)]);
+        assert_eq!(edges[0], edge((0, 0), (1, 0), Busy));
+    }
+
+    #[test]
+    fn parked() {
+        let edges = local(vec![stop(1), park(), unpark(), start(2), stop(2), start(3)]);
+        assert_eq!(edges, vec![
+            edge((0, 0), (1, 0), Busy),
+            edge((1, 0), (2, 0), Parked),
+            edge((2, 0), (3, 0), Busy),
+            edge((3, 0), (4, 0), Spinning(2)),
+        ]);
+
+        // parking isn't merged into the busy time around it
+        // (the trailing busy and spinning edges are still pending)
+        assert_eq!(trim(edges), vec![
+            edge((0, 0), (1, 0), Busy),
+            edge((1, 0), (2, 0), Parked),
+        ]);
+    }
+
+    #[test]
+    fn waiting_for_progress() {
+        let edges = local(vec![stop(1), progress(false, 1), start(2), stop(2)]);
+        assert_eq!(edges[0], edge((0, 0), (1, 0), Waiting(WaitingCause::Progress)));
+
+        // sending progress is part of the worker's own activity
+        let edges = local(vec![stop(1), progress(true, 0), start(2), stop(2)]);
+        assert_eq!(edges[0], edge((0, 0), (1, 0), Busy));
+    }
+
+    #[test]
+    fn waiting_for_input() {
+        // data sent outside of any operator, e.g. by an `InputHandle`
+        let edges = local(vec![stop(1), message(true, 0, 1), start(2), stop(2)]);
+        assert_eq!(edges[0], edge((0, 0), (1, 0), Waiting(WaitingCause::Input)));
+
+        // data sent by a scheduled operator
+        let edges = local(vec![start(1), message(true, 0, 1), stop(1), start(2)]);
+        assert_eq!(edges[0], edge((0, 0), (1, 0), processing(1)));
+        assert_eq!(edges[1], edge((1, 0), (2, 0), Processing { oid: Some(1), send: Some(4), recv: None }));
+    }
+
+    #[test]
+    fn guards_are_separate_activities() {
+        // `InputHandle::for_each` pulls a message before entering the guard around the operator's logic
+        let edges = local(vec![start(1), message(false, 1, 0), guard_message(true), message(true, 0, 1), guard_message(false), stop(1), start(2), stop(2)]);
+        assert_eq!(edges, vec![
+            edge((0, 0), (1, 0), processing(1)),
+            edge((1, 0), (2, 0), Processing { oid: Some(1), send: None, recv: Some(4) }),
+            edge((2, 0), (3, 0), GuardedMessage { oid: Some(1), send: None, recv: None }),
+            edge((3, 0), (4, 0), GuardedMessage { oid: Some(1), send: Some(4), recv: None }),
+            edge((4, 0), (5, 0), processing(1)),
+            edge((5, 0), (6, 0), Busy),
+        ]);
+
+        // trimming keeps the guarded time apart from the surrounding processing
+        let next = edge((6, 0), (7, 0), processing(2));
+        assert_eq!(trim(edges.into_iter().chain(Some(next)).collect()), vec![
+            edge((0, 0), (2, 0), Processing { oid: Some(1), send: None, recv: Some(4) }),
+            edge((2, 0), (4, 0), GuardedMessage { oid: Some(1), send: Some(4), recv: None }),
+            edge((4, 0), (6, 0), processing(1)),
+        ]);
+    }
+
+    #[test]
+    fn nested_guards() {
+        // an input drained within a notification
+        let edges = local(vec![
+            start(1), guard_progress(true), message(false, 1, 0), guard_message(true), guard_message(false),
+            message(true, 0, 1), guard_progress(false), stop(1), start(2),
+        ]);
+        assert_eq!(edges, vec![
+            edge((0, 0), (1, 0), processing(1)),
+            edge((1, 0), (2, 0), GuardedProgress { oid: Some(1), send: None, recv: None }),
+            edge((2, 0), (3, 0), GuardedProgress { oid: Some(1), send: None, recv: Some(4) }),
+            edge((3, 0), (4, 0), GuardedMessage { oid: Some(1), send: None, recv: None }),
+            // back in the outer guard
+            edge((4, 0), (5, 0), GuardedProgress { oid: Some(1), send: None, recv: None }),
+            edge((5, 0), (6, 0), GuardedProgress { oid: Some(1), send: Some(4), recv: None }),
+            edge((6, 0), (7, 0), processing(1)),
+        ]);
+    }
+
+    /// Pushes all edges through a trimmer, returning the edges it emitted
+    fn trim(edges: Vec<PagEdge>) -> Vec<PagEdge> {
+        let mut trimmer = Trimmer::default();
+        edges.into_iter().filter_map(|e| trimmer.push(e)).collect()
+    }
+
+    #[test]
+    fn trim_merges_processing_of_same_operator() {
+        let trimmed = trim(vec![
+            edge((0, 0), (1, 0), Processing { oid: Some(1), send: Some(2), recv: None }),
+            edge((1, 0), (2, 0), Processing { oid: Some(1), send: Some(3), recv: Some(1) }),
+            edge((2, 0), (3, 0), processing(2)),
+            edge((3, 0), (4, 0), processing(1)),
+        ]);
+
+        assert_eq!(trimmed, vec![
+            edge((0, 0), (2, 0), Processing { oid: Some(1), send: Some(5), recv: Some(1) }),
+            edge((2, 0), (3, 0), processing(2)),
+        ]);
+    }
+
+    #[test]
+    fn trim_absorbs_busy() {
+        let trimmed = trim(vec![
+            // busy after processing extends it
+            edge((0, 0), (1, 0), processing(1)),
+            edge((1, 0), (2, 0), Busy),
+            // busy before processing is folded into it
+            edge((2, 0), (3, 0), Waiting(WaitingCause::Data)),
+            edge((3, 0), (4, 0), Busy),
+            edge((4, 0), (5, 0), Busy),
+            edge((5, 0), (6, 0), Busy),
+        ]);
+
+        assert_eq!(trimmed, vec![
+            edge((0, 0), (2, 0), processing(1)),
+            edge((2, 0), (3, 0), Waiting(WaitingCause::Data)),
+        ]);
+
+        let trimmed = trim(vec![
+            edge((0, 0), (1, 0), Busy),
+            edge((1, 0), (2, 0), processing(1)),
+            edge((2, 0), (3, 0), processing(2)),
+        ]);
+        assert_eq!(trimmed, vec![edge((0, 0), (2, 0), processing(1))]);
+    }
+
+    #[test]
+    fn trim_keeps_parked_separate() {
+        let trimmed = trim(vec![
+            edge((0, 0), (1, 0), Busy),
+            edge((1, 0), (2, 0), Parked),
+            edge((2, 0), (3, 0), Busy),
+            edge((3, 0), (4, 0), processing(1)),
+        ]);
+
+        assert_eq!(trimmed, vec![
+            edge((0, 0), (1, 0), Busy),
+            edge((1, 0), (2, 0), Parked),
+        ]);
+    }
+
+    #[test]
+    fn trim_is_per_worker() {
+        let trimmed = trim(vec![
+            edge((0, 0), (1, 0), processing(1)),
+            edge((0, 1), (1, 1), processing(2)),
+            edge((1, 0), (2, 0), processing(1)),
+            edge((1, 1), (2, 1), processing(1)),
+        ]);
+
+        assert_eq!(trimmed, vec![edge((0, 1), (1, 1), processing(2))]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Helpers shared by unit tests: logging real Timely computations and
+//! constructing their PAG with the dataflow pipeline.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use timely::communication::allocator::Generic;
+use timely::dataflow::InputHandle;
+use timely::dataflow::operators::{Exchange, Input, Inspect, Probe, ToStream};
+use timely::logging::TimelyEvent;
+use timely::worker::Worker;
+use timely::Data;
+
+use crate::{Event, PagEdge, PagNode, EdgeType};
+use crate::pag::PagBuilder;
+use crate::analysis::{Analysis, Analyze};
+use crate::topology::Topology;
+
+/// Runs `computation` on `workers` worker threads and returns the events they logged,
+/// in the order they were logged per worker.
+pub(crate) fn log_events<F>(workers: usize, computation: F) -> Vec<Event>
+where F: Fn(&mut Worker<Generic>) + Send + Sync + 'static
+{
+    let events: Arc<Mutex<Vec<Event>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+
+    timely::execute(timely::Configuration::Process(workers), move |worker| {
+        let sink = sink.clone();
+        worker
+            .log_register()
+            .insert::<TimelyEvent, _>("timely", move |_time, data| {
+                sink.lock().unwrap().append(data);
+            });
+
+        computation(worker);
+    }).unwrap();
+
+    // loggers are flushed once their worker has shut down
+    let events = events.lock().unwrap().clone();
+    events
+}
+
+/// Events of a small exchanging computation, similar to `examples/minimal.rs`.
+pub(crate) fn exchange_events(workers: usize, rounds: usize) -> Vec<Event> {
+    log_events(workers, move |worker| {
+        let mut input = InputHandle::new();
+        let probe = worker.dataflow(|scope| {
+            scope
+                .input_from(&mut input)
+                .exchange(|x: &usize| *x as u64 + 1)
+                .inspect(|_| {})
+                .probe()
+        });
+
+        for round in 0 .. rounds {
+            if round % worker.peers() == worker.index() {
+                input.send(round);
+            }
+            input.advance_to(round + 1);
+            while probe.less_than(input.time()) {
+                worker.step();
+            }
+        }
+    })
+}
+
+/// Events of two dataflows exchanging data, driven in lockstep.
+pub(crate) fn two_dataflow_events(workers: usize, rounds: u64) -> Vec<Event> {
+    log_events(workers, move |worker| {
+        let mut input1 = InputHandle::new();
+        let mut input2 = InputHandle::new();
+        let probe1 = worker.dataflow(|scope| scope.input_from(&mut input1).exchange(|x: &u64| *x).probe());
+        let probe2 = worker.dataflow(|scope| scope.input_from(&mut input2).exchange(|x: &u64| *x).probe());
+        for round in 0 .. rounds {
+            input1.send(round);
+            input2.send(round);
+            input1.advance_to(round + 1);
+            input2.advance_to(round + 1);
+            while probe1.less_than(input1.time()) || probe2.less_than(input2.time()) {
+                worker.step();
+            }
+        }
+    })
+}
+
+/// Constructs the PAG of `events` with `PagBuilder` in a single-worker dataflow.
+pub(crate) fn dataflow_pag(events: Vec<Event>, trim: bool) -> Vec<PagEdge> {
+    dataflows_pag(events, trim, None)
+}
+
+/// Like `dataflow_pag`, but restricted to `dataflows`, if provided.
+pub(crate) fn dataflows_pag(events: Vec<Event>, trim: bool, dataflows: Option<BTreeSet<usize>>) -> Vec<PagEdge> {
+    let edges: Arc<Mutex<Vec<PagEdge>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = edges.clone();
+
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<Duration, _, _>(move |scope| {
+            let stream = events.to_stream(scope);
+            let mut builder = PagBuilder::new(&stream).trim(trim);
+            if let Some(dataflows) = dataflows {
+                builder = builder.dataflows(dataflows);
+            }
+            builder.sink(move |edge| sink.lock().unwrap().push(edge.clone()));
+        });
+    });
+
+    let edges = edges.lock().unwrap().clone();
+    edges
+}
+
+/// Edges in a canonical order, s.t. PAGs can be compared regardless of construction order
+pub(crate) fn sorted(mut edges: Vec<PagEdge>) -> Vec<PagEdge> {
+    edges.sort_by_key(|e| (e.src.t, e.src.wid, e.dst.t, e.dst.wid, e.edge_type));
+    edges
+}
+
+/// An edge from `src` to `dst` (in ns), on worker `wid` if local
+pub(crate) fn edge(src: (u64, usize), dst: (u64, usize), edge_type: EdgeType) -> PagEdge {
+    PagEdge {
+        src: PagNode { t: Duration::from_nanos(src.0), wid: src.1 },
+        dst: PagNode { t: Duration::from_nanos(dst.0), wid: dst.1 },
+        edge_type,
+    }
+}
+
+/// Processing of operator `oid`, without any messages
+pub(crate) fn processing(oid: usize) -> EdgeType {
+    EdgeType::Processing { oid: Some(oid), send: None, recv: None }
+}
+
+/// Runs `analysis` as `Analyze` would, handing it the edges of `pag` and advancing
+/// it in steps of `step`, and returns all its results.
+pub(crate) fn run_incrementally<A: Analysis>(mut analysis: A, pag: &[PagEdge], step: Duration, topology: &Topology) -> Vec<A::Output> {
+    // local edges are complete once they started, remote ones once they were both sent and received
+    let complete = |e: &PagEdge| if e.src.wid == e.dst.wid { e.src.t } else { e.src.t.max(e.dst.t) };
+    let mut edges: Vec<&PagEdge> = pag.iter().collect();
+    edges.sort_by_key(|e| complete(e));
+
+    let mut results = Vec::new();
+    let mut edges = edges.into_iter().peekable();
+    let mut until = step;
+    while edges.peek().is_some() {
+        while let Some(edge) = edges.next_if(|e| complete(e) < until) {
+            analysis.update(edge);
+        }
+        results.extend(analysis.advance(until, topology));
+        until += step;
+    }
+    results.extend(analysis.finish(topology));
+    results
+}
+
+/// Runs `analysis` with `Analyze` on the PAG of `events`, constructed in a single-worker
+/// dataflow whose input is advanced to the time of every event, and returns its results.
+pub(crate) fn dataflow_analysis<A>(mut events: Vec<Event>, analysis: A) -> Vec<A::Output>
+where A: Analysis + Send + 'static, A::Output: Data + Send
+{
+    let results: Arc<Mutex<Vec<A::Output>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = results.clone();
+
+    // stable, so every worker's events stay in the order they were logged
+    events.sort_by_key(|x| x.0);
+
+    // the closure has to be `Sync`
+    let analysis = Mutex::new(Some(analysis));
+
+    timely::execute_directly(move |worker| {
+        let mut input = InputHandle::new();
+        let sink = sink.clone();
+        let analysis = analysis.lock().unwrap().take().expect("single worker");
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let stream = scope.input_from(&mut input);
+            PagBuilder::new(&stream)
+                .build()
+                .analyze(&stream, analysis)
+                .inspect(move |x| sink.lock().unwrap().push(x.clone()));
+        });
+
+        for event in events {
+            input.advance_to(event.0);
+            input.send(event);
+            worker.step();
+        }
+    });
+
+    let results = std::mem::take(&mut *results.lock().unwrap());
+    results
+}
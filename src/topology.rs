@@ -0,0 +1,328 @@
+//! Reconstruction of the source computation's dataflow graph from
+//! `Operates` and `Channels` events, s.t. PAG activity can be related
+//! back to the logical plan. `Shutdown` events determine operator lifetimes.
+
+#![deny(missing_docs)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::time::Duration;
+
+use timely::logging::TimelyEvent::{Operates, Channels, Shutdown};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{Event, EdgeType};
+
+/// An operator of the source computation
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Operator {
+    /// Worker-unique operator ID (same on all workers)
+    pub id: usize,
+    /// Address of the operator, i.e. the path of scope indices from the root
+    pub addr: Vec<usize>,
+    /// Name of the operator
+    pub name: String,
+    /// Time the operator was created on the first worker
+    pub created: Duration,
+    /// Time the operator was shut down on the last worker, if it has been
+    /// shut down on all workers it was created on
+    pub shutdown: Option<Duration>,
+    /// workers the operator is alive on
+    #[serde(skip)]
+    alive: BTreeSet<usize>,
+    /// latest shutdown on any worker, as workers' events may arrive in any order
+    #[serde(skip)]
+    last_shutdown: Option<Duration>,
+}
+
+/// A channel connecting two operators of the source computation
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Channel {
+    /// Worker-unique channel ID (same on all workers)
+    pub id: usize,
+    /// Address of the scope containing the channel
+    pub scope_addr: Vec<usize>,
+    /// Source operator index (within the scope) and output port
+    pub source: (usize, usize),
+    /// Target operator index (within the scope) and input port
+    pub target: (usize, usize),
+}
+
+impl Channel {
+    /// Address of the source operator. Index `0` refers to the scope itself.
+    pub fn source_addr(&self) -> Vec<usize> {
+        self.child_addr(self.source.0)
+    }
+
+    /// Address of the target operator. Index `0` refers to the scope itself.
+    pub fn target_addr(&self) -> Vec<usize> {
+        self.child_addr(self.target.0)
+    }
+
+    fn child_addr(&self, index: usize) -> Vec<usize> {
+        let mut addr = self.scope_addr.clone();
+        if index != 0 {
+            addr.push(index);
+        }
+        addr
+    }
+}
+
+/// The dataflow graph of the source computation.
+/// Operator and channel IDs are the same on all workers, so `Operates` and `Channels`
+/// events of several workers describe the same operators and channels. Lifetimes,
+/// however, span all workers: feed events of every worker (in the order they were
+/// logged per worker), otherwise they only reflect the workers that were fed.
+#[derive(Clone, Default, Debug)]
+pub struct Topology {
+    /// Operators by ID
+    pub operators: BTreeMap<usize, Operator>,
+    /// Channels by ID
+    pub channels: BTreeMap<usize, Channel>,
+}
+
+impl Topology {
+    /// Reconstructs the topology from a sequence of log events.
+    pub fn from_events<'a, I: IntoIterator<Item = &'a Event>>(events: I) -> Self {
+        let mut topology = Topology::default();
+        for event in events {
+            topology.update(event);
+        }
+        topology
+    }
+
+    /// Incorporates a log event. Events other than `Operates`, `Channels`
+    /// and `Shutdown` are ignored.
+    pub fn update(&mut self, event: &Event) {
+        let (t, wid, x) = event;
+        match x {
+            Operates(e) => {
+                let op = self.operators.entry(e.id).or_insert_with(|| Operator {
+                    id: e.id,
+                    addr: e.addr.clone(),
+                    name: e.name.clone(),
+                    created: *t,
+                    shutdown: None,
+                    alive: BTreeSet::new(),
+                    last_shutdown: None,
+                });
+                op.created = std::cmp::min(op.created, *t);
+                op.shutdown = None;
+                op.alive.insert(*wid);
+            }
+            Shutdown(e) => {
+                // operators inside a scope aren't shut down on their own when the scope is
+                let addr = match self.operators.get(&e.id) {
+                    Some(op) => op.addr.clone(),
+                    None => return,
+                };
+                for op in self.operators.values_mut().filter(|op| op.addr.starts_with(&addr)) {
+                    if op.alive.remove(wid) {
+                        op.last_shutdown = std::cmp::max(op.last_shutdown, Some(*t));
+                        if op.alive.is_empty() {
+                            op.shutdown = op.last_shutdown;
+                        }
+                    }
+                }
+            }
+            Channels(e) => {
+                self.channels.entry(e.id).or_insert_with(|| Channel {
+                    id: e.id,
+                    scope_addr: e.scope_addr.clone(),
+                    source: e.source,
+                    target: e.target,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// The dataflows of the source computation, i.e. the outermost operators.
+    /// Their lifetimes are the lifetimes of the dataflows.
+    pub fn dataflows(&self) -> impl Iterator<Item = &Operator> {
+        self.operators.values().filter(|op| op.addr.len() == 1)
+    }
+
+    /// Looks up an operator by its address.
+    pub fn operator_at(&self, addr: &[usize]) -> Option<&Operator> {
+        self.operators.values().find(|op| op.addr == addr)
+    }
+
+    /// The dataflow an activity belongs to, i.e. that of its operator or channel.
+    /// `None` for activities not attributed to either, or if they're unknown.
+    pub fn dataflow_of(&self, edge_type: &EdgeType) -> Option<usize> {
+        if let Some(oid) = edge_type.oid() {
+            return self.operators.get(&oid)?.addr.first().copied();
+        }
+        if let Some(channel) = edge_type.channel() {
+            return self.channels.get(&channel)?.scope_addr.first().copied();
+        }
+        None
+    }
+
+    /// Looks up the producing and consuming operator of a channel, e.g. to
+    /// attribute `Data` edges. If an endpoint is the channel's scope boundary,
+    /// the scope operator itself is returned.
+    pub fn channel_operators(&self, channel: usize) -> Option<(&Operator, &Operator)> {
+        let ch = self.channels.get(&channel)?;
+        let source = self.operator_at(&ch.source_addr())?;
+        let target = self.operator_at(&ch.target_addr())?;
+        Some((source, target))
+    }
+
+    /// Exports the topology as JSON, with operators and channels as arrays.
+    pub fn to_json(&self) -> String {
+        serde_json::json!({
+            "operators": self.operators.values().collect::<Vec<_>>(),
+            "channels": self.channels.values().collect::<Vec<_>>(),
+        }).to_string()
+    }
+
+    /// Exports the topology as a Graphviz DOT digraph.
+    /// Nodes are operators (identified by address), edges are channels.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph topology {\n");
+
+        for op in self.operators.values() {
+            let shutdown = op.shutdown.map(|t| format!("{:?}", t)).unwrap_or_else(|| "-".to_string());
+            writeln!(dot, "  \"{}\" [label=\"{} ({}) {:?}\\n{:?} .. {}\"];",
+                     dot_id(&op.addr), op.name.replace('"', "\\\""), op.id, op.addr, op.created, shutdown).unwrap();
+        }
+
+        for ch in self.channels.values() {
+            writeln!(dot, "  \"{}\" -> \"{}\" [label=\"{} ({}:{})\"];",
+                     dot_id(&ch.source_addr()), dot_id(&ch.target_addr()),
+                     ch.id, ch.source.1, ch.target.1).unwrap();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn dot_id(addr: &[usize]) -> String {
+    addr.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::exchange_events;
+
+    use timely::logging::{OperatesEvent, ShutdownEvent, ChannelsEvent};
+
+    fn operates(t: u64, wid: usize, id: usize, addr: Vec<usize>) -> Event {
+        (Duration::from_nanos(t), wid, Operates(OperatesEvent { id, addr, name: format!("op{}", id) }))
+    }
+
+    fn shutdown(t: u64, wid: usize, id: usize) -> Event {
+        (Duration::from_nanos(t), wid, Shutdown(ShutdownEvent { id }))
+    }
+
+    fn channel(id: usize, scope_addr: Vec<usize>, source: (usize, usize), target: (usize, usize)) -> Event {
+        (Duration::default(), 0, Channels(ChannelsEvent { id, scope_addr, source, target }))
+    }
+
+    /// A dataflow with an input feeding a nested scope, which contains a single operator
+    fn nested() -> Topology {
+        Topology::from_events(&[
+            operates(1, 0, 0, vec![0]),
+            operates(2, 0, 1, vec![0, 1]),
+            operates(3, 0, 2, vec![0, 2]),
+            operates(4, 0, 3, vec![0, 2, 1]),
+            // input -> scope
+            channel(10, vec![0], (1, 0), (2, 0)),
+            // scope input -> inner operator -> scope output
+            channel(11, vec![0, 2], (0, 0), (1, 0)),
+            channel(12, vec![0, 2], (1, 0), (0, 0)),
+        ])
+    }
+
+    #[test]
+    fn lifetimes_span_all_workers() {
+        let mut topology = Topology::default();
+        for event in &[operates(1, 0, 0, vec![0]), operates(2, 1, 0, vec![0]), operates(3, 1, 1, vec![0, 1])] {
+            topology.update(event);
+        }
+        assert_eq!(topology.operators.len(), 2);
+        assert_eq!(topology.operators[&0].created, Duration::from_nanos(1));
+
+        topology.update(&shutdown(5, 0, 0));
+        assert_eq!(topology.operators[&0].shutdown, None);
+
+        // shutting down the dataflow shuts down the operators it contains
+        topology.update(&shutdown(7, 1, 0));
+        assert_eq!(topology.operators[&0].shutdown, Some(Duration::from_nanos(7)));
+        assert_eq!(topology.operators[&1].shutdown, Some(Duration::from_nanos(7)));
+    }
+
+    #[test]
+    fn lifetimes_regardless_of_worker_order() {
+        // worker 1's events arrive before worker 0's
+        let events = [operates(2, 1, 0, vec![0]), shutdown(9, 1, 0), operates(1, 0, 0, vec![0]), shutdown(5, 0, 0)];
+        let topology = Topology::from_events(events.iter());
+        assert_eq!(topology.operators[&0].created, Duration::from_nanos(1));
+        assert_eq!(topology.operators[&0].shutdown, Some(Duration::from_nanos(9)));
+    }
+
+    #[test]
+    fn channels_across_nested_scopes() {
+        let topology = nested();
+        let ids = |channel| topology.channel_operators(channel).map(|(source, target)| (source.id, target.id));
+        assert_eq!(ids(10), Some((1, 2)));
+        // the scope is the channel's endpoint on its boundary
+        assert_eq!(ids(11), Some((2, 3)));
+        assert_eq!(ids(12), Some((3, 2)));
+        assert_eq!(ids(13), None);
+
+        assert_eq!(topology.dataflow_of(&EdgeType::Data { length: 1, channel: 11 }), Some(0));
+        assert_eq!(topology.dataflow_of(&EdgeType::Spinning(3)), Some(0));
+        assert_eq!(topology.dataflow_of(&EdgeType::Data { length: 1, channel: 13 }), None);
+        assert_eq!(topology.dataflow_of(&EdgeType::Busy), None);
+    }
+
+    #[test]
+    fn channels_of_a_computation() {
+        let topology = Topology::from_events(exchange_events(1, 1).iter());
+        assert!(!topology.channels.is_empty());
+        assert!(topology.channels.keys().all(|ch| topology.channel_operators(*ch).is_some()));
+    }
+
+    #[test]
+    fn exports_json() {
+        let mut topology = nested();
+        topology.update(&shutdown(9, 0, 2));
+
+        let json: serde_json::Value = serde_json::from_str(&topology.to_json()).unwrap();
+        let operators = json["operators"].as_array().unwrap();
+        assert_eq!(operators.len(), 4);
+        assert_eq!(operators[3], serde_json::json!({
+            "id": 3,
+            "addr": [0, 2, 1],
+            "name": "op3",
+            "created": { "secs": 0, "nanos": 4 },
+            "shutdown": { "secs": 0, "nanos": 9 },
+        }));
+        assert_eq!(operators[0]["shutdown"], serde_json::Value::Null);
+        assert_eq!(json["channels"][1], serde_json::json!({ "id": 11, "scope_addr": [0, 2], "source": [0, 0], "target": [1, 0] }));
+    }
+
+    #[test]
+    fn exports_dot() {
+        let mut topology = Topology::from_events(&[
+            operates(1, 0, 0, vec![0]),
+            operates(3, 0, 1, vec![0, 1]),
+            channel(10, vec![0], (1, 0), (0, 1)),
+        ]);
+        topology.update(&shutdown(7, 0, 0));
+
+        assert_eq!(topology.to_dot(), r#"digraph topology {
+  "0" [label="op0 (0) [0]\n1ns .. 7ns"];
+  "0_1" [label="op1 (1) [0, 1]\n3ns .. 7ns"];
+  "0_1" -> "0" [label="10 (0:1)"];
+}
+"#);
+    }
+}